@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS messages (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    text TEXT NOT NULL,
+    context_id TEXT NOT NULL,
+    context_name TEXT NOT NULL,
+    created_time TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    UNIQUE (user_id, sequence)
+);
+
+CREATE INDEX IF NOT EXISTS messages_context_id ON messages (context_id, sequence);
@@ -0,0 +1,4 @@
+ALTER TABLE messages ADD COLUMN image_url TEXT;
+ALTER TABLE messages ADD COLUMN preview_image_url TEXT;
+
+CREATE INDEX IF NOT EXISTS messages_created_time ON messages (created_time, user_id, sequence);
@@ -0,0 +1 @@
+ALTER TABLE messages ADD COLUMN image_revised_prompt TEXT;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS usage (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    context_id TEXT,
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    images INTEGER NOT NULL,
+    cost REAL NOT NULL,
+    created_time TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS usage_user_id ON usage (user_id, created_time);
+CREATE INDEX IF NOT EXISTS usage_created_time ON usage (created_time);
@@ -0,0 +1,11 @@
+-- messages saved before timestamps were fixed-width were encoded by sqlx as
+-- 2024-10-20T12:34:56.123+00:00, with 0, 3, 6 or 9 fractional digits, which
+-- doesn't sort as text against 2024-10-20T12:34:56.123000Z
+UPDATE messages
+SET created_time = substr(created_time, 1, 19) || '.' || substr(
+    CASE
+        WHEN substr(created_time, 20, 1) = '.'
+            THEN substr(created_time, 21, instr(created_time, '+') - 21)
+        ELSE ''
+    END || '000000', 1, 6) || 'Z'
+WHERE created_time LIKE '%+00:00';
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS users (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    image_size TEXT,
+    image_orientation TEXT,
+    image_count INTEGER,
+    reply_language TEXT,
+    persona TEXT,
+    verbosity TEXT,
+    history_opt_out INTEGER NOT NULL
+);
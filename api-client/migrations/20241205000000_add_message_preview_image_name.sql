@@ -0,0 +1 @@
+ALTER TABLE messages ADD COLUMN preview_image_name TEXT;
@@ -0,0 +1,15 @@
+use api_client::firestore::MessageRepoImpl;
+
+// numbers the Firestore messages saved before sequence numbers existed, run it once per database
+#[tokio::main]
+async fn main() {
+    let repo = MessageRepoImpl::new()
+        .await
+        .expect("Failed to initialize Firestore message repository");
+    let count = repo
+        .backfill_sequences()
+        .await
+        .expect("Failed to backfill message sequences");
+
+    println!("Numbered {} messages", count);
+}
@@ -1,26 +1,110 @@
 use api_client::gpt::Gpt;
-use domain::UserDemand;
+use domain::{Actor, ImageEdit, ImageMessage, Message, User, UserDemand};
 
 #[tokio::main]
 async fn main() {
     let llm_client = Gpt::new().expect("Failed to initialize OpenAI API client");
+    let message = |from: Actor, text: &str| Message {
+        user: User {
+            id: "1234567890".to_string(),
+        },
+        from,
+        text: text.to_string(),
+        context: None,
+        reply_token: None,
+        image: None,
+        quick_replies: vec![],
+        timestamp: None,
+    };
 
-    let text = "レシピを10個考えて";
-    let (context, user_demand) = llm_client.detect_demand(text.to_string()).await.unwrap();
-    println!("Context: {:#?}", context);
+    let detection = llm_client
+        .detect_demand(vec![message(Actor::User, "レシピを10個考えて")])
+        .await
+        .unwrap();
+    println!("Detection: {:#?}", detection);
     assert!(
-        matches!(user_demand, UserDemand::Chat),
+        matches!(detection.demand, UserDemand::Chat),
         "Expected Chat, got {:?}",
-        user_demand
+        detection.demand
     );
 
-    let text = "ミーアキャットが逆立ちしている画像をつくって";
-    let (context, user_demand) = llm_client.detect_demand(text.to_string()).await.unwrap();
-    println!("Context: {:#?}", context);
+    let detection = llm_client
+        .detect_demand(vec![message(
+            Actor::User,
+            "ミーアキャットが逆立ちしている画像をつくって",
+        )])
+        .await
+        .unwrap();
+    println!("Detection: {:#?}", detection);
     assert!(
-        matches!(user_demand, UserDemand::CreateImage),
+        matches!(detection.demand, UserDemand::CreateImage),
         "Expected CreateImage, got {:?}",
-        user_demand
+        detection.demand
+    );
+
+    let detection = llm_client
+        .detect_demand(vec![message(Actor::User, "さっきの画像を白黒にして")])
+        .await
+        .unwrap();
+    println!("Detection: {:#?}", detection);
+    assert!(
+        matches!(&detection.demand, UserDemand::EditImage(edits) if edits == &[ImageEdit::Grayscale]),
+        "Expected EditImage([Grayscale]), got {:?}",
+        detection.demand
+    );
+
+    // only the history tells that "it" is the image just sent
+    let image = Message {
+        image: Some(ImageMessage {
+            url: "https://example.com/image.png".to_string(),
+            preview_url: "https://example.com/preview.png".to_string(),
+            name: None,
+            preview_name: None,
+            revised_prompt: None,
+        }),
+        ..message(Actor::Bot, "")
+    };
+    let detection = llm_client
+        .detect_demand(vec![
+            message(Actor::User, "ミーアキャットの画像をつくって"),
+            image,
+            message(Actor::User, "make it bigger"),
+        ])
+        .await
+        .unwrap();
+    println!("Detection: {:#?}", detection);
+    assert!(
+        matches!(&detection.demand, UserDemand::EditImage(edits) if edits == &[ImageEdit::Upscale]),
+        "Expected EditImage([Upscale]), got {:?}",
+        detection.demand
+    );
+
+    let detection = llm_client
+        .detect_demand(vec![message(
+            Actor::User,
+            "Translate \"good morning\" into French",
+        )])
+        .await
+        .unwrap();
+    println!("Detection: {:#?}", detection);
+    assert!(
+        matches!(detection.demand, UserDemand::Translate),
+        "Expected Translate, got {:?}",
+        detection.demand
+    );
+
+    let detection = llm_client
+        .detect_demand(vec![message(
+            Actor::User,
+            "10分後に洗濯物を取り込むよう教えて",
+        )])
+        .await
+        .unwrap();
+    println!("Detection: {:#?}", detection);
+    assert!(
+        matches!(detection.demand, UserDemand::Reminder),
+        "Expected Reminder, got {:?}",
+        detection.demand
     );
 
     println!("All tests passed!");
@@ -0,0 +1,36 @@
+use api_client::gpt::{Gpt, ImageOptions};
+use domain::Image;
+
+#[tokio::main]
+async fn main() {
+    let llm_client = Gpt::new().expect("Failed to initialize OpenAI API client");
+    let options = ImageOptions {
+        count: Some(1),
+        ..Default::default()
+    };
+
+    let generated = llm_client
+        .generate_image("ミーアキャット".to_string(), &options)
+        .await
+        .unwrap();
+    let image = Image::from_base64(generated.images[0].b64_json.clone()).unwrap();
+
+    let edited = llm_client
+        .edit_image(&image, None, "帽子をかぶせて".to_string(), &options)
+        .await
+        .unwrap();
+    assert_eq!(edited.images.len(), 1);
+    Image::from_base64(edited.images[0].b64_json.clone())
+        .unwrap()
+        .save("./".to_string())
+        .unwrap();
+
+    let variation = llm_client.create_variation(&image, &options).await.unwrap();
+    assert_eq!(variation.images.len(), 1);
+    Image::from_base64(variation.images[0].b64_json.clone())
+        .unwrap()
+        .save("./".to_string())
+        .unwrap();
+
+    println!("OK");
+}
@@ -1,18 +1,49 @@
-use api_client::gpt::Gpt;
-use domain::Image;
+use api_client::gpt::{Gpt, ImageOptions};
+use chrono::Utc;
+use domain::{Image, ImageBudget, ImageProvenance};
 
 #[tokio::main]
 async fn main() {
     let llm_client = Gpt::new().expect("Failed to initialize OpenAI API client");
 
     let text = "ミーアキャット";
-    let image_base64 = llm_client.generate_image(text.to_string()).await.unwrap();
+    let generated = llm_client
+        .generate_image(text.to_string(), &ImageOptions::default())
+        .await
+        .unwrap();
 
-    let image = Image::from_base64(image_base64[0].clone());
-    image.save("./".to_string()).unwrap();
+    println!("Revised prompt: {:?}", generated.images[0].revised_prompt);
 
-    let image = Image::from_base64(image_base64[0].clone()).to_preview();
+    let provenance = ImageProvenance {
+        prompt: text.to_string(),
+        model: generated.model.clone(),
+        size: generated.size.clone(),
+        created_time: Utc::now(),
+        context_id: None,
+    };
+    let image = Image::from_base64(generated.images[0].b64_json.clone())
+        .unwrap()
+        .with_provenance(&provenance)
+        .unwrap();
+    assert_eq!(image.provenance(), Some(provenance.clone()));
+    println!(
+        "Original: {}x{} {}",
+        image.width,
+        image.height,
+        image.format.mime_type()
+    );
     image.save("./".to_string()).unwrap();
 
+    let preview = image.to_preview().unwrap();
+    assert_eq!(preview.provenance(), Some(provenance));
+    assert!(preview.size() <= ImageBudget::PREVIEW.max_bytes);
+    println!(
+        "Preview: {}x{}, {} bytes",
+        preview.width,
+        preview.height,
+        preview.size()
+    );
+    preview.save("./".to_string()).unwrap();
+
     println!("OK");
 }
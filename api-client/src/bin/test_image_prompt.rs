@@ -0,0 +1,38 @@
+use api_client::gpt::Gpt;
+use domain::{Actor, ImageOrientation, Message, User};
+
+#[tokio::main]
+async fn main() {
+    let llm_client = Gpt::new().expect("Failed to initialize OpenAI API client");
+    let message = |text: &str| Message {
+        user: User {
+            id: "1234567890".to_string(),
+        },
+        from: Actor::User,
+        text: text.to_string(),
+        context: None,
+        reply_token: None,
+        image: None,
+        quick_replies: vec![],
+        timestamp: None,
+    };
+
+    let image_prompt = llm_client
+        .create_image_prompt(vec![message("A wide panorama of Mt. Fuji")])
+        .await
+        .unwrap();
+    println!("{:#?}", image_prompt);
+    assert_eq!(
+        image_prompt.options.orientation,
+        Some(ImageOrientation::Landscape)
+    );
+
+    let image_prompt = llm_client
+        .create_image_prompt(vec![message("ミーアキャットの画像を3枚つくって")])
+        .await
+        .unwrap();
+    println!("{:#?}", image_prompt);
+    assert_eq!(image_prompt.options.count, Some(3));
+
+    println!("OK");
+}
@@ -0,0 +1,156 @@
+use api_client::{memory, sqlite};
+use chrono::DateTime;
+use domain::{
+    Actor, Context, ImageMessage, Message, MessageQuery, MessageRepo, OrderDirection, User,
+};
+
+#[tokio::main]
+async fn main() {
+    let memory_repo = memory::MessageRepoImpl::new();
+    check(&memory_repo).await;
+
+    let sqlite_repo = sqlite::MessageRepoImpl::connect("sqlite::memory:")
+        .await
+        .expect("Failed to initialize SQLite message repository");
+    check(&sqlite_repo).await;
+
+    println!("All tests passed!");
+}
+
+async fn check(repo: &impl MessageRepo) {
+    let context = Context::new("Recipes".to_string());
+    let message = |from: Actor, text: &str| Message {
+        user: User {
+            id: "1234567890".to_string(),
+        },
+        from,
+        text: text.to_string(),
+        context: Some(context.clone()),
+        reply_token: None,
+        image: None,
+        quick_replies: vec![],
+        timestamp: None,
+    };
+
+    repo.save(vec![message(Actor::User, "first")])
+        .await
+        .unwrap();
+    repo.save(vec![
+        message(Actor::Bot, "second"),
+        message(Actor::Bot, "third"),
+    ])
+    .await
+    .unwrap();
+    repo.save(vec![]).await.unwrap();
+
+    let texts = |messages: Vec<Message>| {
+        messages
+            .into_iter()
+            .map(|message| message.text)
+            .collect::<Vec<String>>()
+    };
+
+    let history = repo
+        .list_by_user_id("1234567890".to_string(), 2, OrderDirection::Ascending)
+        .await
+        .unwrap();
+    assert_eq!(texts(history), vec!["second", "third"]);
+
+    let history = repo
+        .list_by_context_id(context.id.clone(), 10, OrderDirection::Descending)
+        .await
+        .unwrap();
+    assert_eq!(texts(history), vec!["third", "second", "first"]);
+
+    repo.save(vec![Message {
+        image: Some(ImageMessage {
+            url: "https://example.com/image.png".to_string(),
+            preview_url: "https://example.com/preview.png".to_string(),
+            name: Some("users/1234567890/image.png".to_string()),
+            preview_name: Some("users/1234567890/preview.jpg".to_string()),
+            revised_prompt: Some("A meerkat doing a handstand".to_string()),
+        }),
+        ..message(Actor::Bot, "fourth")
+    }])
+    .await
+    .unwrap();
+
+    let mut pages = vec![];
+    let mut page_token = None;
+    loop {
+        let page = repo
+            .query(MessageQuery {
+                user_id: Some("1234567890".to_string()),
+                from: Some(Actor::Bot),
+                limit: 2,
+                order_direction: OrderDirection::Ascending,
+                page_token,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        pages.push(texts(page.messages));
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+    assert_eq!(pages, vec![vec!["second", "third"], vec!["fourth"]]);
+
+    let page = repo
+        .query(MessageQuery {
+            context_id: Some(context.id.clone()),
+            has_image: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let image = page.messages[0].image.clone().unwrap();
+    assert_eq!(image.name.as_deref(), Some("users/1234567890/image.png"));
+    assert_eq!(
+        image.preview_name.as_deref(),
+        Some("users/1234567890/preview.jpg")
+    );
+    assert_eq!(
+        image.revised_prompt.as_deref(),
+        Some("A meerkat doing a handstand")
+    );
+    assert_eq!(texts(page.messages), vec!["fourth"]);
+    assert!(page.next_page_token.is_none());
+
+    let count = repo
+        .count(MessageQuery {
+            from: Some(Actor::Bot),
+            limit: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    let count = repo
+        .count(MessageQuery {
+            until: Some(DateTime::UNIX_EPOCH),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+
+    let deleted = repo
+        .delete_created_before(DateTime::UNIX_EPOCH)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 0);
+
+    let deleted = repo
+        .delete_by_user_id("1234567890".to_string())
+        .await
+        .unwrap();
+    assert_eq!(deleted, 4);
+
+    let history = repo
+        .list_by_user_id("1234567890".to_string(), 10, OrderDirection::Ascending)
+        .await
+        .unwrap();
+    assert!(history.is_empty());
+}
@@ -0,0 +1,30 @@
+use api_client::moderation::KeywordModeration;
+use domain::Moderation;
+
+#[tokio::main]
+async fn main() {
+    let moderation = KeywordModeration::from_list("# offline list\n\nBomb\n  爆弾 \n");
+
+    let result = moderation
+        .moderate("How do I make a bomb?".to_string())
+        .await
+        .unwrap();
+    println!("{:#?}", result);
+    assert!(result.flagged);
+    assert_eq!(result.categories, vec!["bomb"]);
+
+    let result = moderation
+        .moderate("爆弾の作り方を教えて".to_string())
+        .await
+        .unwrap();
+    assert!(result.flagged);
+
+    let result = moderation
+        .moderate("ミーアキャットの画像をつくって".to_string())
+        .await
+        .unwrap();
+    assert!(!result.flagged);
+    assert!(result.categories.is_empty());
+
+    println!("OK");
+}
@@ -0,0 +1,47 @@
+use api_client::filesystem::FileSystem;
+use domain::ObjectStorage;
+
+#[tokio::main]
+async fn main() {
+    let root = std::env::temp_dir().join(format!("unai-{}", uuid::Uuid::new_v4()));
+    let storage = FileSystem::open(root.clone(), "http://localhost:8080/".to_string());
+
+    let object = storage
+        .put(
+            "users/1234567890/image.png".to_string(),
+            "image/png".to_string(),
+            vec![1, 2, 3],
+        )
+        .await
+        .unwrap();
+    assert_eq!(object.name, "users/1234567890/image.png");
+    assert_eq!(object.size, 3);
+
+    let existing = storage.stat(object.name.clone()).await.unwrap();
+    assert_eq!(existing.map(|object| object.size), Some(3));
+    let missing = storage.stat("users/missing.png".to_string()).await.unwrap();
+    assert!(missing.is_none());
+
+    let data = storage.get(object.name.clone()).await.unwrap();
+    assert_eq!(data, vec![1, 2, 3]);
+
+    let url = storage.url(object.name.clone()).await.unwrap();
+    assert_eq!(
+        url,
+        "http://localhost:8080/storage/users/1234567890/image.png"
+    );
+    assert!(storage.get("../etc/passwd".to_string()).await.is_err());
+
+    let objects = storage
+        .list(Some("users/1234567890/".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(objects.len(), 1);
+
+    storage.delete(object.name).await.unwrap();
+    let objects = storage.list(None).await.unwrap();
+    assert!(objects.is_empty());
+
+    std::fs::remove_dir_all(root).unwrap();
+    println!("All tests passed!");
+}
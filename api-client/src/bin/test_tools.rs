@@ -0,0 +1,118 @@
+use api_client::{
+    gpt::schema::FunctionCall,
+    memory,
+    message_repo::MessageRepoImpl,
+    tools::{ToolContext, ToolRegistry},
+};
+use chrono::DateTime;
+use domain::{Actor, Context, Message, MessageRepo, User};
+
+#[tokio::main]
+async fn main() {
+    let message_repo = memory::MessageRepoImpl::new();
+    let context = Context::new("Recipes".to_string());
+    let message = |from: Actor, text: &str, timestamp: &str| Message {
+        user: User {
+            id: "1234567890".to_string(),
+        },
+        from,
+        text: text.to_string(),
+        context: Some(context.clone()),
+        reply_token: None,
+        image: None,
+        quick_replies: vec![],
+        timestamp: Some(DateTime::parse_from_rfc3339(timestamp).unwrap().into()),
+    };
+    message_repo
+        .save(vec![
+            message(
+                Actor::User,
+                "Tell me a curry recipe",
+                "2024-10-01T10:00:00Z",
+            ),
+            message(Actor::Bot, "Here is a Curry recipe", "2024-10-01T10:00:01Z"),
+            message(
+                Actor::User,
+                "Tell me a pasta recipe",
+                "2024-10-05T10:00:00Z",
+            ),
+        ])
+        .await
+        .unwrap();
+
+    let tools = ToolRegistry::builtin(MessageRepoImpl::Memory(message_repo));
+    let tool_context = ToolContext {
+        user_id: "1234567890".to_string(),
+        history_opt_out: false,
+    };
+    let call = |name: &str, arguments: &str| FunctionCall {
+        name: name.to_string(),
+        arguments: arguments.to_string(),
+    };
+
+    let result = tools
+        .call(
+            &call(
+                "calculator",
+                r#"{"expression": "-2^2 + (1.5 + 2) * 4 % 5"}"#,
+            ),
+            &tool_context,
+        )
+        .await;
+    assert_eq!(result, "0");
+    let result = tools
+        .call(
+            &call("calculator", r#"{"expression": "1 / 0"}"#),
+            &tool_context,
+        )
+        .await;
+    assert!(result.starts_with("Error"), "{}", result);
+
+    let result = tools
+        .call(
+            &call("current_time", r#"{"utc_offset_minutes": 540}"#),
+            &tool_context,
+        )
+        .await;
+    println!("Current time: {}", result);
+    assert!(result.contains("+09:00"));
+
+    let result = tools
+        .call(
+            &call(
+                "search_past_conversations",
+                r#"{"keywords": ["curry"], "until": "2024-10-03"}"#,
+            ),
+            &tool_context,
+        )
+        .await;
+    println!("Search:\n{}", result);
+    assert_eq!(result.lines().count(), 2);
+    let result = tools
+        .call(
+            &call(
+                "search_past_conversations",
+                r#"{"keywords": ["curry"], "since": "2024-10-02"}"#,
+            ),
+            &tool_context,
+        )
+        .await;
+    assert_eq!(result, "No matching messages");
+
+    // the search is neither offered nor run for users who opted out of history
+    let opted_out = ToolContext {
+        history_opt_out: true,
+        ..tool_context.clone()
+    };
+    assert_eq!(tools.definitions(&tool_context).unwrap().len(), 3);
+    assert_eq!(tools.definitions(&opted_out).unwrap().len(), 2);
+    let result = tools
+        .call(
+            &call("search_past_conversations", r#"{"keywords": ["curry"]}"#),
+            &opted_out,
+        )
+        .await;
+    assert!(result.starts_with("Error"), "{}", result);
+
+    println!("OK");
+}
@@ -0,0 +1,101 @@
+use api_client::{memory, sqlite, usage::PriceTable};
+use chrono::{DateTime, Duration, Utc};
+use domain::{ContextId, UsageQuery, UsageRecord, UsageRepo, UsageSummary};
+
+#[tokio::main]
+async fn main() {
+    let prices = PriceTable::new().expect("Failed to load price table");
+    // the snapshot is priced as gpt-4o-mini, not as gpt-4o
+    assert_eq!(
+        prices.cost("gpt-4o-mini-2024-07-18", 1_000_000, 1_000_000, 0),
+        0.75
+    );
+    assert_eq!(prices.cost("dall-e-3", 0, 0, 2), 0.08);
+    assert_eq!(prices.cost("unknown-model", 1_000, 1_000, 1), 0.0);
+
+    let memory_repo = memory::UsageRepoImpl::new();
+    check(&memory_repo).await;
+
+    let sqlite_repo = sqlite::UsageRepoImpl::connect("sqlite::memory:")
+        .await
+        .expect("Failed to initialize SQLite usage repository");
+    check(&sqlite_repo).await;
+
+    println!("All tests passed!");
+}
+
+async fn check(repo: &impl UsageRepo) {
+    let day = DateTime::parse_from_rfc3339("2024-11-20T10:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let record = |user_id: &str, created_time: DateTime<Utc>, images: u64| UsageRecord {
+        user_id: user_id.to_string(),
+        context_id: Some(ContextId::new()),
+        model: "gpt-4o".to_string(),
+        prompt_tokens: 100,
+        completion_tokens: 50,
+        images,
+        cost: 0.01,
+        created_time,
+    };
+
+    repo.save(vec![
+        record("alice", day, 0),
+        record("alice", day + Duration::hours(1), 2),
+        record("alice", day + Duration::days(1), 0),
+        record("bob", day, 1),
+    ])
+    .await
+    .unwrap();
+    repo.save(vec![]).await.unwrap();
+
+    let records = repo
+        .query(UsageQuery {
+            user_id: Some("alice".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 3);
+    assert!(records[0].context_id.is_some());
+
+    let summaries = UsageSummary::by_day(&repo.query(UsageQuery::default()).await.unwrap());
+    let totals = summaries
+        .iter()
+        .map(|summary| {
+            (
+                summary.user_id.as_str(),
+                summary.day.to_string(),
+                summary.requests,
+                summary.total_tokens(),
+                summary.images,
+            )
+        })
+        .collect::<Vec<(&str, String, u64, u64, u64)>>();
+    assert_eq!(
+        totals,
+        vec![
+            ("alice", "2024-11-20".to_string(), 2, 300, 2),
+            ("bob", "2024-11-20".to_string(), 1, 150, 1),
+            ("alice", "2024-11-21".to_string(), 1, 150, 0),
+        ]
+    );
+    assert!((summaries[0].cost - 0.02).abs() < 1e-9);
+
+    let records = repo
+        .query(UsageQuery {
+            since: Some(day + Duration::days(1)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 1);
+
+    assert_eq!(
+        repo.delete_by_user_id("alice".to_string()).await.unwrap(),
+        3
+    );
+    let records = repo.query(UsageQuery::default()).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].user_id, "bob");
+}
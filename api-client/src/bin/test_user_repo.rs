@@ -0,0 +1,85 @@
+use api_client::{memory, sqlite};
+use domain::{ImageOrientation, ImageSizePreference, UserPreferences, UserRepo, Verbosity};
+
+#[tokio::main]
+async fn main() {
+    let memory_repo = memory::UserRepoImpl::new();
+    check(&memory_repo).await;
+
+    let sqlite_repo = sqlite::UserRepoImpl::connect("sqlite::memory:")
+        .await
+        .expect("Failed to initialize SQLite user repository");
+    check(&sqlite_repo).await;
+
+    println!("All tests passed!");
+}
+
+async fn check(repo: &impl UserRepo) {
+    // users who never changed anything get the defaults
+    let preferences = repo
+        .get_preferences("1234567890".to_string())
+        .await
+        .unwrap();
+    assert!(preferences.image_size.is_none());
+    assert!(!preferences.history_opt_out);
+
+    repo.save_preferences(
+        "1234567890".to_string(),
+        UserPreferences {
+            image_size: Some(ImageSizePreference::Large),
+            image_orientation: Some(ImageOrientation::Portrait),
+            image_count: Some(4),
+            reply_language: Some("Japanese".to_string()),
+            persona: Some("friend".to_string()),
+            verbosity: Some(Verbosity::Concise),
+            history_opt_out: true,
+        },
+    )
+    .await
+    .unwrap();
+    // saved again, the previous preferences are replaced
+    repo.save_preferences(
+        "1234567890".to_string(),
+        UserPreferences {
+            image_count: Some(2),
+            ..repo
+                .get_preferences("1234567890".to_string())
+                .await
+                .unwrap()
+        },
+    )
+    .await
+    .unwrap();
+
+    let preferences = repo
+        .get_preferences("1234567890".to_string())
+        .await
+        .unwrap();
+    assert_eq!(preferences.image_size, Some(ImageSizePreference::Large));
+    assert_eq!(
+        preferences.image_orientation,
+        Some(ImageOrientation::Portrait)
+    );
+    assert_eq!(preferences.image_count, Some(2));
+    assert_eq!(preferences.reply_language.as_deref(), Some("Japanese"));
+    assert_eq!(preferences.persona.as_deref(), Some("friend"));
+    assert_eq!(preferences.verbosity, Some(Verbosity::Concise));
+    assert!(preferences.history_opt_out);
+
+    // other users are not affected
+    let preferences = repo
+        .get_preferences("0987654321".to_string())
+        .await
+        .unwrap();
+    assert!(preferences.image_count.is_none());
+
+    repo.delete_preferences("1234567890".to_string())
+        .await
+        .unwrap();
+    let preferences = repo
+        .get_preferences("1234567890".to_string())
+        .await
+        .unwrap();
+    assert!(preferences.image_count.is_none());
+    assert!(!preferences.history_opt_out);
+}
@@ -0,0 +1,166 @@
+use chrono::prelude::*;
+use domain::{ObjectStorage, StoredObject};
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+
+// stores objects under a local directory, the server serves them at /storage/{name}
+#[derive(Clone, Debug)]
+pub struct FileSystem {
+    root: PathBuf,
+    base_url: String,
+}
+
+impl FileSystem {
+    pub fn new() -> Result<Self, &'static str> {
+        let root = std::env::var("LOCAL_STORAGE_DIR")
+            .expect("Please set the LOCAL_STORAGE_DIR environment variable");
+        let base_url = std::env::var("PUBLIC_BASE_URL")
+            .expect("Please set the PUBLIC_BASE_URL environment variable");
+
+        Ok(Self::open(root.into(), base_url))
+    }
+
+    pub fn open(root: PathBuf, base_url: String) -> Self {
+        Self {
+            root,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // object names must stay inside the storage directory
+    fn path(&self, name: &str) -> Result<PathBuf, &'static str> {
+        let relative = Path::new(name);
+        if name.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err("Invalid object name");
+        }
+
+        Ok(self.root.join(relative))
+    }
+
+    async fn stored_object(&self, path: &Path) -> Result<StoredObject, &'static str> {
+        let metadata = fs::metadata(path)
+            .await
+            .map_err(|_| "Failed to read object metadata")?;
+        let name = path
+            .strip_prefix(&self.root)
+            .map_err(|_| "Object is outside of the storage directory")?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        Ok(StoredObject {
+            name,
+            size: metadata.len(),
+            // not every filesystem records the creation time
+            created_time: metadata
+                .created()
+                .or(metadata.modified())
+                .ok()
+                .map(DateTime::<Utc>::from),
+        })
+    }
+}
+
+impl ObjectStorage for FileSystem {
+    async fn put(
+        &self,
+        name: String,
+        _content_type: String,
+        data: Vec<u8>,
+    ) -> Result<StoredObject, &'static str> {
+        let path = self.path(&name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|_| "Failed to create object directory")?;
+        }
+        fs::write(&path, data)
+            .await
+            .map_err(|_| "Failed to write object")?;
+
+        self.stored_object(&path).await
+    }
+
+    async fn get(&self, name: String) -> Result<Vec<u8>, &'static str> {
+        fs::read(self.path(&name)?)
+            .await
+            .map_err(|_| "Failed to read object")
+    }
+
+    async fn stat(&self, name: String) -> Result<Option<StoredObject>, &'static str> {
+        let path = self.path(&name)?;
+        match fs::try_exists(&path).await {
+            Ok(true) => Ok(Some(self.stored_object(&path).await?)),
+            Ok(false) => Ok(None),
+            Err(_) => Err("Failed to read object metadata"),
+        }
+    }
+
+    async fn delete(&self, name: String) -> Result<(), &'static str> {
+        fs::remove_file(self.path(&name)?)
+            .await
+            .map_err(|_| "Failed to delete object")
+    }
+
+    async fn list(&self, prefix: Option<String>) -> Result<Vec<StoredObject>, &'static str> {
+        let mut objects = vec![];
+        let mut directories = vec![self.root.clone()];
+        while let Some(directory) = directories.pop() {
+            let mut entries = match fs::read_dir(&directory).await {
+                Ok(entries) => entries,
+                // nothing has been stored yet
+                Err(_) if directory == self.root => break,
+                Err(_) => return Err("Failed to list objects"),
+            };
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|_| "Failed to list objects")?
+            {
+                let path = entry.path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else {
+                    objects.push(self.stored_object(&path).await?);
+                }
+            }
+        }
+
+        Ok(objects
+            .into_iter()
+            .filter(|object| {
+                prefix
+                    .as_ref()
+                    .is_none_or(|prefix| object.name.starts_with(prefix))
+            })
+            .collect())
+    }
+
+    async fn url(&self, name: String) -> Result<String, &'static str> {
+        self.path(&name)?;
+
+        Ok(format!("{}/storage/{}", self.base_url, name))
+    }
+}
+
+// the filesystem does not keep the content type, so it is derived from the extension
+pub fn content_type(name: &str) -> &'static str {
+    match Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
@@ -1,49 +1,236 @@
+use crate::message_repo::{into_page, MessageKey};
 use chrono::prelude::*;
-use domain::{Actor, Message, MessageRepo, OrderDirection, User};
+use domain::{
+    Actor, Context, ContextId, ImageMessage, ImageOrientation, ImageSizePreference, Message,
+    MessagePage, MessageQuery, MessageRepo, OrderDirection, UsageQuery, UsageRecord, UsageRepo,
+    User, UserPreferences, UserRepo, Verbosity,
+};
 use firestore::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
+// a Firestore transaction accepts at most 500 writes
+const WRITE_BATCH_SIZE: u32 = 500;
+
 #[derive(Clone, Debug)]
 pub struct MessageRepoImpl {
     db: FirestoreDb,
 }
 
+async fn connect() -> Result<FirestoreDb, &'static str> {
+    let project_id = std::env::var("GOOGLE_PROJECT_ID").expect("GOOGLE_PROJECT_ID is not set");
+    let database_id = std::env::var("FIRESTORE_DB_ID").expect("FIRESTORE_DB_ID is not set");
+    let credentials = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
+        .expect("GOOGLE_APPLICATION_CREDENTIALS is not set");
+
+    Ok(FirestoreDb::with_options_service_account_key_file(
+        FirestoreDbOptions::new(project_id).with_database_id(database_id),
+        credentials.into(),
+    )
+    .await
+    .expect("Failed to initilize Firestore client"))
+}
+
+// deletes the documents in one transaction, so at most WRITE_BATCH_SIZE at a time
+async fn delete_documents(
+    db: &FirestoreDb,
+    collection_id: &str,
+    documents: Vec<FirestoreDocument>,
+) -> Result<u64, &'static str> {
+    let mut transaction = db
+        .begin_transaction()
+        .await
+        .map_err(|_| "Failed to begin Firestore transaction")?;
+
+    for document in documents.iter() {
+        // document names end with the document id
+        let document_id = document.name.rsplit('/').next().unwrap_or_default();
+        db.fluent()
+            .delete()
+            .from(collection_id)
+            .document_id(document_id)
+            .add_to_transaction(&mut transaction)
+            .map_err(|_| "Failed to add deletion to Firestore transaction")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|_| "Failed to delete documents from Firestore")?;
+
+    Ok(documents.len() as u64)
+}
+
 impl MessageRepoImpl {
     pub async fn new() -> Result<Self, &'static str> {
-        let project_id = std::env::var("GOOGLE_PROJECT_ID").expect("GOOGLE_PROJECT_ID is not set");
-        let database_id = std::env::var("FIRESTORE_DB_ID").expect("FIRESTORE_DB_ID is not set");
-        let credentials = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
-            .expect("GOOGLE_APPLICATION_CREDENTIALS is not set");
-
         Ok(Self {
-            db: FirestoreDb::with_options_service_account_key_file(
-                FirestoreDbOptions::new(project_id).with_database_id(database_id),
-                credentials.into(),
-            )
-            .await
-            .expect("Failed to initilize Firestore client"),
+            db: connect().await?,
         })
     }
+
+    // messages saved before sequence numbers existed are left out of every query ordered by them.
+    // the messages of users who have any are renumbered in the order they were created.
+    // users without such messages are not touched, so it can run more than once
+    pub async fn backfill_sequences(&self) -> Result<u64, &'static str> {
+        let documents = self
+            .db
+            .fluent()
+            .select()
+            .from("messages")
+            .order_by([("createdTime", FirestoreQueryDirection::Ascending)])
+            .obj::<SequenceDocument>()
+            .query()
+            .await
+            .map_err(|_| "Failed to get messages from Firestore")?;
+
+        let mut users = BTreeMap::<String, Vec<SequenceDocument>>::new();
+        for document in documents {
+            users
+                .entry(document.user_id.clone())
+                .or_default()
+                .push(document);
+        }
+
+        let mut count = 0;
+        for (user_id, mut documents) in users {
+            let legacy = documents
+                .iter()
+                .filter(|document| document.sequence.is_none())
+                .count();
+            if legacy == 0 {
+                continue;
+            }
+
+            // messages of a turn share their created time, their sequence keeps them in order
+            documents.sort_by_key(|document| (document.created_time, document.sequence));
+            let last_sequence = documents.len() as i64;
+            let sequences = documents
+                .iter()
+                .zip(1..)
+                .filter(|(document, sequence)| document.sequence != Some(*sequence))
+                .map(|(document, sequence)| (document.id.as_str(), sequence))
+                .collect::<Vec<(&str, i64)>>();
+
+            // the counter is written with the last batch, once every message is numbered
+            let mut batches = sequences.chunks(WRITE_BATCH_SIZE as usize - 1).peekable();
+            while let Some(batch) = batches.next() {
+                let mut transaction = self
+                    .db
+                    .begin_transaction()
+                    .await
+                    .map_err(|_| "Failed to begin Firestore transaction")?;
+
+                for (document_id, sequence) in batch {
+                    self.db
+                        .fluent()
+                        .update()
+                        .fields(["sequence"])
+                        .in_col("messages")
+                        .precondition(FirestoreWritePrecondition::Exists(true))
+                        .document_id(document_id)
+                        .object(&SequenceUpdate {
+                            sequence: *sequence,
+                        })
+                        .add_to_transaction(&mut transaction)
+                        .map_err(|_| "Failed to add sequence to Firestore transaction")?;
+                }
+
+                if batches.peek().is_none() {
+                    self.db
+                        .fluent()
+                        .update()
+                        .in_col("conversations")
+                        .document_id(&user_id)
+                        .object(&ConversationDocument { last_sequence })
+                        .add_to_transaction(&mut transaction)
+                        .map_err(|_| "Failed to add conversation to Firestore transaction")?;
+                }
+
+                transaction
+                    .commit()
+                    .await
+                    .map_err(|_| "Failed to save sequences to Firestore")?;
+            }
+
+            count += legacy as u64;
+        }
+
+        Ok(count)
+    }
 }
 
 impl MessageRepo for MessageRepoImpl {
     async fn save(&self, messages: Vec<Message>) -> Result<(), &'static str> {
-        let message: MessageDocument = messages[0]
-            .clone()
-            .try_into()
-            .expect("Invalid message to insert into DB");
+        let Some(user_id) = messages.first().map(|message| message.user.id.clone()) else {
+            return Ok(());
+        };
+        if messages.iter().any(|message| message.user.id != user_id) {
+            return Err("Messages must belong to the same user");
+        }
 
-        let _: MessageDocument = self
+        // write every message in a single transaction so a turn is saved all or nothing
+        let mut transaction = self
             .db
+            .begin_transaction()
+            .await
+            .map_err(|_| "Failed to begin Firestore transaction")?;
+
+        // read the conversation counter inside the transaction so concurrent turns conflict
+        let transaction_db =
+            self.db
+                .clone_with_consistency_selector(FirestoreConsistencySelector::Transaction(
+                    transaction.transaction_id().clone(),
+                ));
+        let conversation: Option<ConversationDocument> = transaction_db
             .fluent()
-            .insert()
-            .into("messages")
-            .document_id(Uuid::new_v4().to_string())
-            .object(&message)
-            .execute()
+            .select()
+            .by_id_in("conversations")
+            .obj()
+            .one(&user_id)
             .await
-            .expect("Failed to save messages to Firestore");
+            .map_err(|_| "Failed to get conversation from Firestore")?;
+        let last_sequence = conversation.map_or(0, |conversation| conversation.last_sequence);
+
+        let saved_time = Utc::now();
+        let documents = messages
+            .into_iter()
+            .zip(last_sequence + 1..)
+            .map(|(message, sequence)| MessageDocument::new(message, saved_time, sequence))
+            .collect::<Result<Vec<MessageDocument>, &'static str>>()?;
+        let document_ids = documents
+            .iter()
+            .map(|_| Uuid::new_v4().to_string())
+            .collect::<Vec<String>>();
+        let conversation = ConversationDocument {
+            last_sequence: last_sequence + documents.len() as i64,
+        };
+
+        for (document_id, document) in document_ids.iter().zip(documents.iter()) {
+            self.db
+                .fluent()
+                .update()
+                .in_col("messages")
+                .precondition(FirestoreWritePrecondition::Exists(false))
+                .document_id(document_id)
+                .object(document)
+                .add_to_transaction(&mut transaction)
+                .map_err(|_| "Failed to add message to Firestore transaction")?;
+        }
+
+        self.db
+            .fluent()
+            .update()
+            .in_col("conversations")
+            .document_id(&user_id)
+            .object(&conversation)
+            .add_to_transaction(&mut transaction)
+            .map_err(|_| "Failed to add conversation to Firestore transaction")?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| "Failed to save messages to Firestore")?;
 
         Ok(())
     }
@@ -60,22 +247,207 @@ impl MessageRepo for MessageRepoImpl {
             .select()
             .from("messages")
             .filter(|q| q.field("userId").eq(&user_id))
-            .order_by([("createdTime", FirestoreQueryDirection::Descending)])
+            .order_by([("sequence", FirestoreQueryDirection::Descending)])
             .limit(limit)
             .obj::<MessageDocument>()
             .query()
             .await
-            .expect("Failed to get messages from Firestore");
+            .map_err(|_| "Failed to get messages from Firestore")?;
+
+        into_messages(messages, order_direction)
+    }
 
-        match order_direction {
-            OrderDirection::Ascending => {
-                messages.into_iter().rev().map(TryInto::try_into).collect()
+    async fn list_by_context_id(
+        &self,
+        context_id: ContextId,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        let context_id = context_id.to_string();
+        let messages = self
+            .db
+            .fluent()
+            .select()
+            .from("messages")
+            .filter(|q| q.field("contextId").eq(&context_id))
+            .order_by([("sequence", FirestoreQueryDirection::Descending)])
+            .limit(limit)
+            .obj::<MessageDocument>()
+            .query()
+            .await
+            .map_err(|_| "Failed to get messages from Firestore")?;
+
+        into_messages(messages, order_direction)
+    }
+
+    async fn query(&self, query: MessageQuery) -> Result<MessagePage, &'static str> {
+        let cursor = query
+            .page_token
+            .as_deref()
+            .map(MessageKey::from_page_token)
+            .transpose()?;
+        let direction = match query.order_direction {
+            OrderDirection::Ascending => FirestoreQueryDirection::Ascending,
+            OrderDirection::Descending => FirestoreQueryDirection::Descending,
+        };
+
+        let select = self
+            .db
+            .fluent()
+            .select()
+            .from("messages")
+            .filter(|q| message_filter(q, &query))
+            // sequence is only unique per user, so the user breaks ties between users
+            .order_by([
+                ("createdTime", direction.clone()),
+                ("userId", direction.clone()),
+                ("sequence", direction),
+            ])
+            .limit(query.limit + 1);
+        let select = match cursor {
+            Some(cursor) => select.start_at(FirestoreQueryCursor::AfterValue(vec![
+                FirestoreTimestamp(cursor.created_time).into(),
+                cursor.user_id.into(),
+                cursor.sequence.into(),
+            ])),
+            None => select,
+        };
+
+        let documents = select
+            .obj::<MessageDocument>()
+            .query()
+            .await
+            .map_err(|_| "Failed to query messages from Firestore")?;
+
+        into_page(documents, query.limit, |document| MessageKey {
+            created_time: document.created_time,
+            user_id: document.user_id.clone(),
+            sequence: document.sequence,
+        })
+    }
+
+    async fn count(&self, query: MessageQuery) -> Result<u64, &'static str> {
+        // counted by Firestore, the documents are never read
+        let counts: Vec<MessageCount> = self
+            .db
+            .fluent()
+            .select()
+            .from("messages")
+            .filter(|q| message_filter(q, &query))
+            .aggregate(|a| a.fields([a.field("count").count()]))
+            .obj()
+            .query()
+            .await
+            .map_err(|_| "Failed to count messages in Firestore")?;
+
+        Ok(counts
+            .first()
+            .map(|counts| counts.count)
+            .unwrap_or_default())
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        let mut count = 0;
+        loop {
+            let documents = self
+                .db
+                .fluent()
+                .select()
+                .from("messages")
+                .filter(|q| q.field("userId").eq(&user_id))
+                .limit(WRITE_BATCH_SIZE)
+                .query()
+                .await
+                .map_err(|_| "Failed to get messages from Firestore")?;
+            if documents.is_empty() {
+                break;
             }
-            OrderDirection::Descending => messages.into_iter().map(TryInto::try_into).collect(),
+
+            count += delete_documents(&self.db, "messages", documents).await?;
         }
+
+        self.db
+            .fluent()
+            .delete()
+            .from("conversations")
+            .document_id(&user_id)
+            .execute()
+            .await
+            .map_err(|_| "Failed to delete conversation from Firestore")?;
+
+        Ok(count)
+    }
+
+    async fn delete_created_before(&self, until: DateTime<Utc>) -> Result<u64, &'static str> {
+        let mut count = 0;
+        loop {
+            let documents = self
+                .db
+                .fluent()
+                .select()
+                .from("messages")
+                .filter(|q| q.field("createdTime").less_than(FirestoreTimestamp(until)))
+                .limit(WRITE_BATCH_SIZE)
+                .query()
+                .await
+                .map_err(|_| "Failed to get messages from Firestore")?;
+            if documents.is_empty() {
+                break;
+            }
+
+            count += delete_documents(&self.db, "messages", documents).await?;
+        }
+
+        Ok(count)
     }
 }
 
+// documents are queried newest first
+fn into_messages(
+    documents: Vec<MessageDocument>,
+    order_direction: OrderDirection,
+) -> Result<Vec<Message>, &'static str> {
+    match order_direction {
+        OrderDirection::Ascending => documents.into_iter().rev().map(TryInto::try_into).collect(),
+        OrderDirection::Descending => documents.into_iter().map(TryInto::try_into).collect(),
+    }
+}
+
+// every filter of a message query, shared by the select and the count
+fn message_filter(
+    q: select_filter_builder::FirestoreQueryFilterBuilder,
+    query: &MessageQuery,
+) -> Option<FirestoreQueryFilter> {
+    q.for_all([
+        query
+            .user_id
+            .as_ref()
+            .and_then(|user_id| q.field("userId").eq(user_id)),
+        query
+            .context_id
+            .as_ref()
+            .and_then(|context_id| q.field("contextId").eq(context_id.to_string())),
+        query
+            .from
+            .and_then(|from| q.field("from").eq(Sender::from(from))),
+        query.since.and_then(|since| {
+            q.field("createdTime")
+                .greater_than_or_equal(FirestoreTimestamp(since))
+        }),
+        query
+            .until
+            .and_then(|until| q.field("createdTime").less_than(FirestoreTimestamp(until))),
+        query
+            .has_image
+            .and_then(|has_image| q.field("hasImage").eq(has_image)),
+    ])
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageCount {
+    count: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct MessageDocument {
@@ -87,6 +459,41 @@ struct MessageDocument {
 
     #[serde(with = "firestore::serialize_as_timestamp")]
     created_time: DateTime<Utc>,
+    sequence: i64,
+
+    // stored separately so messages can be filtered by whether they carry an image
+    #[serde(default)]
+    has_image: bool,
+    image_url: Option<String>,
+    preview_image_url: Option<String>,
+    image_name: Option<String>,
+    preview_image_name: Option<String>,
+    image_revised_prompt: Option<String>,
+}
+
+// the fields needed to number messages saved before sequence numbers existed
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SequenceDocument {
+    #[serde(alias = "_firestore_id")]
+    id: String,
+    user_id: String,
+
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    created_time: DateTime<Utc>,
+    sequence: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SequenceUpdate {
+    sequence: i64,
+}
+
+// per-user counter that hands out message sequence numbers
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ConversationDocument {
+    last_sequence: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -114,19 +521,35 @@ impl From<Sender> for Actor {
     }
 }
 
-impl TryFrom<Message> for MessageDocument {
-    type Error = &'static str;
-
-    fn try_from(message: Message) -> Result<Self, Self::Error> {
-        let context = message.context.expect("Context is required");
+impl MessageDocument {
+    fn new(
+        message: Message,
+        saved_time: DateTime<Utc>,
+        sequence: i64,
+    ) -> Result<Self, &'static str> {
+        let context = message.context.ok_or("Context is required")?;
 
         Ok(Self {
             user_id: message.user.id,
             from: message.from.into(),
-            text: message.text.clone(),
+            text: message.text,
             context_id: context.id.to_string(),
             context_name: context.name,
-            created_time: Utc::now(),
+            // prefer when the message was actually sent over when it reached the DB
+            created_time: message.timestamp.unwrap_or(saved_time),
+            sequence,
+            has_image: message.image.is_some(),
+            image_url: message.image.as_ref().map(|image| image.url.clone()),
+            preview_image_url: message
+                .image
+                .as_ref()
+                .map(|image| image.preview_url.clone()),
+            image_name: message.image.as_ref().and_then(|image| image.name.clone()),
+            preview_image_name: message
+                .image
+                .as_ref()
+                .and_then(|image| image.preview_name.clone()),
+            image_revised_prompt: message.image.and_then(|image| image.revised_prompt),
         })
     }
 }
@@ -141,9 +564,314 @@ impl TryFrom<MessageDocument> for Message {
             },
             from: doc.from.into(),
             text: doc.text,
-            context: None,
+            context: Some(Context {
+                id: doc.context_id.try_into()?,
+                name: doc.context_name,
+            }),
             reply_token: None,
-            image: None,
+            quick_replies: vec![],
+            image: doc
+                .image_url
+                .zip(doc.preview_image_url)
+                .map(|(url, preview_url)| ImageMessage {
+                    url,
+                    preview_url,
+                    name: doc.image_name,
+                    preview_name: doc.preview_image_name,
+                    revised_prompt: doc.image_revised_prompt,
+                }),
+            timestamp: Some(doc.created_time),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UserRepoImpl {
+    db: FirestoreDb,
+}
+
+impl UserRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        Ok(Self {
+            db: connect().await?,
+        })
+    }
+}
+
+impl UserRepo for UserRepoImpl {
+    async fn get_preferences(&self, user_id: String) -> Result<UserPreferences, &'static str> {
+        let user: Option<UserDocument> = self
+            .db
+            .fluent()
+            .select()
+            .by_id_in("users")
+            .obj()
+            .one(&user_id)
+            .await
+            .map_err(|_| "Failed to get user from Firestore")?;
+
+        Ok(user.map(Into::into).unwrap_or_default())
+    }
+
+    async fn save_preferences(
+        &self,
+        user_id: String,
+        preferences: UserPreferences,
+    ) -> Result<(), &'static str> {
+        let _: UserDocument = self
+            .db
+            .fluent()
+            .update()
+            .in_col("users")
+            .document_id(&user_id)
+            .object(&UserDocument::from(preferences))
+            .execute()
+            .await
+            .map_err(|_| "Failed to save user to Firestore")?;
+
+        Ok(())
+    }
+
+    async fn delete_preferences(&self, user_id: String) -> Result<(), &'static str> {
+        self.db
+            .fluent()
+            .delete()
+            .from("users")
+            .document_id(&user_id)
+            .execute()
+            .await
+            .map_err(|_| "Failed to delete user from Firestore")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct UserDocument {
+    image_size: Option<ImageSizeField>,
+    image_orientation: Option<ImageOrientationField>,
+    image_count: Option<u8>,
+    reply_language: Option<String>,
+    persona: Option<String>,
+    verbosity: Option<VerbosityField>,
+    history_opt_out: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum ImageSizeField {
+    Small,
+    Medium,
+    Large,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum ImageOrientationField {
+    Square,
+    Landscape,
+    Portrait,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum VerbosityField {
+    Concise,
+    Normal,
+    Detailed,
+}
+
+impl From<UserPreferences> for UserDocument {
+    fn from(preferences: UserPreferences) -> Self {
+        Self {
+            image_size: preferences.image_size.map(|size| match size {
+                ImageSizePreference::Small => ImageSizeField::Small,
+                ImageSizePreference::Medium => ImageSizeField::Medium,
+                ImageSizePreference::Large => ImageSizeField::Large,
+            }),
+            image_orientation: preferences
+                .image_orientation
+                .map(|orientation| match orientation {
+                    ImageOrientation::Square => ImageOrientationField::Square,
+                    ImageOrientation::Landscape => ImageOrientationField::Landscape,
+                    ImageOrientation::Portrait => ImageOrientationField::Portrait,
+                }),
+            image_count: preferences.image_count,
+            reply_language: preferences.reply_language,
+            persona: preferences.persona,
+            verbosity: preferences.verbosity.map(|verbosity| match verbosity {
+                Verbosity::Concise => VerbosityField::Concise,
+                Verbosity::Normal => VerbosityField::Normal,
+                Verbosity::Detailed => VerbosityField::Detailed,
+            }),
+            history_opt_out: preferences.history_opt_out,
+        }
+    }
+}
+
+impl From<UserDocument> for UserPreferences {
+    fn from(doc: UserDocument) -> Self {
+        Self {
+            image_size: doc.image_size.map(|size| match size {
+                ImageSizeField::Small => ImageSizePreference::Small,
+                ImageSizeField::Medium => ImageSizePreference::Medium,
+                ImageSizeField::Large => ImageSizePreference::Large,
+            }),
+            image_orientation: doc.image_orientation.map(|orientation| match orientation {
+                ImageOrientationField::Square => ImageOrientation::Square,
+                ImageOrientationField::Landscape => ImageOrientation::Landscape,
+                ImageOrientationField::Portrait => ImageOrientation::Portrait,
+            }),
+            image_count: doc.image_count,
+            reply_language: doc.reply_language,
+            persona: doc.persona,
+            verbosity: doc.verbosity.map(|verbosity| match verbosity {
+                VerbosityField::Concise => Verbosity::Concise,
+                VerbosityField::Normal => Verbosity::Normal,
+                VerbosityField::Detailed => Verbosity::Detailed,
+            }),
+            history_opt_out: doc.history_opt_out,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UsageRepoImpl {
+    db: FirestoreDb,
+}
+
+impl UsageRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        Ok(Self {
+            db: connect().await?,
+        })
+    }
+}
+
+impl UsageRepo for UsageRepoImpl {
+    async fn save(&self, records: Vec<UsageRecord>) -> Result<(), &'static str> {
+        let mut transaction = self
+            .db
+            .begin_transaction()
+            .await
+            .map_err(|_| "Failed to begin Firestore transaction")?;
+
+        for record in records {
+            self.db
+                .fluent()
+                .update()
+                .in_col("usage")
+                .precondition(FirestoreWritePrecondition::Exists(false))
+                .document_id(Uuid::new_v4().to_string())
+                .object(&UsageDocument::from(record))
+                .add_to_transaction(&mut transaction)
+                .map_err(|_| "Failed to add usage to Firestore transaction")?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| "Failed to save usage to Firestore")?;
+
+        Ok(())
+    }
+
+    async fn query(&self, query: UsageQuery) -> Result<Vec<UsageRecord>, &'static str> {
+        let documents: Vec<UsageDocument> = self
+            .db
+            .fluent()
+            .select()
+            .from("usage")
+            .filter(|q| {
+                q.for_all([
+                    query
+                        .user_id
+                        .as_ref()
+                        .and_then(|user_id| q.field("userId").eq(user_id)),
+                    query.since.and_then(|since| {
+                        q.field("createdTime")
+                            .greater_than_or_equal(FirestoreTimestamp(since))
+                    }),
+                    query.until.and_then(|until| {
+                        q.field("createdTime").less_than(FirestoreTimestamp(until))
+                    }),
+                ])
+            })
+            .order_by([("createdTime", FirestoreQueryDirection::Ascending)])
+            .obj()
+            .query()
+            .await
+            .map_err(|_| "Failed to query usage from Firestore")?;
+
+        documents.into_iter().map(TryInto::try_into).collect()
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        let mut count = 0;
+        loop {
+            let documents = self
+                .db
+                .fluent()
+                .select()
+                .from("usage")
+                .filter(|q| q.field("userId").eq(&user_id))
+                .limit(WRITE_BATCH_SIZE)
+                .query()
+                .await
+                .map_err(|_| "Failed to get usage from Firestore")?;
+            if documents.is_empty() {
+                break;
+            }
+
+            count += delete_documents(&self.db, "usage", documents).await?;
+        }
+
+        Ok(count)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UsageDocument {
+    user_id: String,
+    context_id: Option<String>,
+    model: String,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    images: u64,
+    cost: f64,
+
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    created_time: DateTime<Utc>,
+}
+
+impl From<UsageRecord> for UsageDocument {
+    fn from(record: UsageRecord) -> Self {
+        Self {
+            user_id: record.user_id,
+            context_id: record.context_id.map(|context_id| context_id.to_string()),
+            model: record.model,
+            prompt_tokens: record.prompt_tokens,
+            completion_tokens: record.completion_tokens,
+            images: record.images,
+            cost: record.cost,
+            created_time: record.created_time,
+        }
+    }
+}
+
+impl TryFrom<UsageDocument> for UsageRecord {
+    type Error = &'static str;
+
+    fn try_from(doc: UsageDocument) -> Result<Self, Self::Error> {
+        Ok(UsageRecord {
+            user_id: doc.user_id,
+            context_id: doc.context_id.map(TryInto::try_into).transpose()?,
+            model: doc.model,
+            prompt_tokens: doc.prompt_tokens,
+            completion_tokens: doc.completion_tokens,
+            images: doc.images,
+            cost: doc.cost,
+            created_time: doc.created_time,
         })
     }
 }
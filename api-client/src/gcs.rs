@@ -1,7 +1,19 @@
-pub use google_cloud_storage::http::objects::Object as GcsObject;
+use crate::object_storage::SIGNED_URL_EXPIRATION;
+use chrono::DateTime;
+use domain::{ObjectStorage, StoredObject};
 use google_cloud_storage::{
     client::{google_cloud_auth::credentials, Client, ClientConfig},
-    http::objects::upload::{Media, UploadObjectRequest, UploadType},
+    http::{
+        objects::{
+            delete::DeleteObjectRequest,
+            download::Range,
+            get::GetObjectRequest,
+            list::ListObjectsRequest,
+            upload::{Media, UploadObjectRequest, UploadType},
+            Object,
+        },
+        Error,
+    },
     sign::{SignedURLMethod, SignedURLOptions},
 };
 use std::env;
@@ -33,13 +45,19 @@ impl Gcs {
 
         Ok(Gcs { client, bucket })
     }
+}
 
-    pub async fn upload(
+impl ObjectStorage for Gcs {
+    async fn put(
         &self,
-        file_name: String,
+        name: String,
+        content_type: String,
         data: Vec<u8>,
-    ) -> Result<GcsObject, &'static str> {
-        let upload_type = UploadType::Simple(Media::new(file_name));
+    ) -> Result<StoredObject, &'static str> {
+        let upload_type = UploadType::Simple(Media {
+            content_type: content_type.into(),
+            ..Media::new(name)
+        });
         let uploaded = self
             .client
             .upload_object(
@@ -51,24 +69,107 @@ impl Gcs {
                 &upload_type,
             )
             .await
-            .expect("Failed to upload object");
+            .map_err(|_| "Failed to upload object")?;
 
-        Ok(uploaded)
+        Ok(stored_object(uploaded))
     }
 
-    pub async fn get_url(&self, uploaded: GcsObject) -> Result<String, &'static str> {
+    async fn get(&self, name: String) -> Result<Vec<u8>, &'static str> {
+        self.client
+            .download_object(
+                &GetObjectRequest {
+                    bucket: self.bucket.clone(),
+                    object: name,
+                    ..Default::default()
+                },
+                &Range::default(),
+            )
+            .await
+            .map_err(|_| "Failed to download object")
+    }
+
+    async fn stat(&self, name: String) -> Result<Option<StoredObject>, &'static str> {
+        let result = self
+            .client
+            .get_object(&GetObjectRequest {
+                bucket: self.bucket.clone(),
+                object: name,
+                ..Default::default()
+            })
+            .await;
+
+        match result {
+            Ok(object) => Ok(Some(stored_object(object))),
+            Err(Error::Response(response)) if response.code == 404 => Ok(None),
+            Err(_) => Err("Failed to get object metadata"),
+        }
+    }
+
+    async fn delete(&self, name: String) -> Result<(), &'static str> {
+        self.client
+            .delete_object(&DeleteObjectRequest {
+                bucket: self.bucket.clone(),
+                object: name,
+                ..Default::default()
+            })
+            .await
+            .map_err(|_| "Failed to delete object")
+    }
+
+    async fn list(&self, prefix: Option<String>) -> Result<Vec<StoredObject>, &'static str> {
+        let mut objects = vec![];
+        let mut page_token = None;
+        loop {
+            let response = self
+                .client
+                .list_objects(&ListObjectsRequest {
+                    bucket: self.bucket.clone(),
+                    prefix: prefix.clone(),
+                    page_token,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|_| "Failed to list objects")?;
+
+            objects.extend(
+                response
+                    .items
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(stored_object),
+            );
+            page_token = response.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn url(&self, name: String) -> Result<String, &'static str> {
         let options = SignedURLOptions {
             method: SignedURLMethod::GET,
-            expires: std::time::Duration::from_secs(600),
+            expires: SIGNED_URL_EXPIRATION,
             ..Default::default()
         };
 
         let url_for_download = self
             .client
-            .signed_url(&uploaded.bucket, &uploaded.name, None, None, options)
+            .signed_url(&self.bucket, &name, None, None, options)
             .await
-            .expect("Failed to get signed URL");
+            .map_err(|_| "Failed to get signed URL")?;
 
         Ok(url_for_download)
     }
 }
+
+fn stored_object(object: Object) -> StoredObject {
+    StoredObject {
+        name: object.name,
+        size: object.size as u64,
+        created_time: object
+            .time_created
+            .and_then(|created| DateTime::from_timestamp(created.unix_timestamp(), 0)),
+    }
+}
@@ -1,14 +1,40 @@
 pub mod schema;
 
-use domain::{Context, UserDemand};
+use crate::{
+    prompts::{PromptVariables, Prompts},
+    tools::{ToolContext, ToolRegistry},
+    usage::{PriceTable, UsageMeter},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use domain::{
+    Context, EncodeFormat, ImageBudget, ImageEdit, ImageOrientation, ImageQuality,
+    ImageSizePreference, ImageStyle, Moderation, ModerationResult, UserDemand, UserPreferences,
+    Verbosity,
+};
+use futures::future;
+use reqwest::multipart::{Form, Part};
 use schema::*;
+use schemars::gen::SchemaSettings;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::json;
 use std::env;
+use std::fmt;
+
+// rounds of tool calls before the chat gives up on an answer
+const MAX_TOOL_ROUNDS: usize = 5;
+
+// returned when the images API refuses the prompt, so callers can tell it from other failures
+pub const CONTENT_POLICY_VIOLATION: &str = "Image request was rejected by the content policy";
 
 #[derive(Clone)]
 pub struct Gpt {
     api_key: String,
+    chat_model: String,
     image_config: ImageConfig,
+    prompts: Prompts,
+    prices: PriceTable,
+    // None for clients that don't account their calls to a user
+    meter: Option<UsageMeter>,
 }
 
 impl Gpt {
@@ -16,37 +42,146 @@ impl Gpt {
         let api_key =
             env::var("OPENAI_API_KEY").expect("Please set the OPENAI_API_KEY environment variable");
 
-        let image_config = ImageConfig::new().expect("Failed to create ImageConfig");
+        let chat_model = env::var("CHAT_MODEL").unwrap_or("gpt-4o".to_string());
+
+        let image_config = ImageConfig::new()?;
+
+        let prompts = Prompts::new()?;
+
+        let prices = PriceTable::new()?;
 
         Ok(Self {
             api_key,
+            chat_model,
             image_config,
+            prompts,
+            prices,
+            meter: None,
         })
     }
 
+    // a client whose calls are recorded in the meter
+    pub fn metered(&self, meter: &UsageMeter) -> Self {
+        Self {
+            meter: Some(meter.clone()),
+            ..self.clone()
+        }
+    }
+
+    fn record_usage(&self, model: &str, prompt_tokens: i64, completion_tokens: i64, images: usize) {
+        let Some(meter) = &self.meter else {
+            return;
+        };
+        let prompt_tokens = u64::try_from(prompt_tokens).unwrap_or(0);
+        let completion_tokens = u64::try_from(completion_tokens).unwrap_or(0);
+        let images = images as u64;
+        let cost = self
+            .prices
+            .cost(model, prompt_tokens, completion_tokens, images);
+
+        meter.record(
+            model.to_string(),
+            prompt_tokens,
+            completion_tokens,
+            images,
+            cost,
+        );
+    }
+
+    pub fn prompts(&self) -> &Prompts {
+        &self.prompts
+    }
+
     pub async fn completions(
         &self,
         request: CompletionsRequest,
+    ) -> Result<CompletionsResponse, reqwest::Error> {
+        self.post_completions(&request).await
+    }
+
+    async fn post_completions(
+        &self,
+        request: &impl Serialize,
     ) -> Result<CompletionsResponse, reqwest::Error> {
         let client = reqwest::Client::new();
         let response = client
             .post("https://api.openai.com/v1/chat/completions")
             .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&request)
+            .json(request)
             .send()
             .await?;
 
-        response.json().await
+        let response: CompletionsResponse = response.json().await?;
+        self.record_usage(
+            &response.model,
+            response.usage.prompt_tokens,
+            response.usage.completion_tokens,
+            0,
+        );
+
+        Ok(response)
     }
 
-    pub async fn generate_image(&self, prompt: String) -> Result<Vec<String>, &'static str> {
-        let request = GenerateImageRequest {
-            model: self.image_config.model.to_string(),
-            prompt,
-            n: self.image_config.count,
-            size: self.image_config.size.to_num(),
-            response_format: Some(GenImageResponseFormat::B64Json),
+    pub async fn generate_image(
+        &self,
+        prompt: String,
+        options: &ImageOptions,
+    ) -> Result<GeneratedImages, &'static str> {
+        let image_model = &self.image_config.model;
+        let capabilities = image_model.capabilities();
+        let size = match (options.orientation, options.size) {
+            (Some(ImageOrientation::Landscape), _) => ImageSize::Landscape,
+            (Some(ImageOrientation::Portrait), _) => ImageSize::Portrait,
+            (_, Some(ImageSizePreference::Small)) => ImageSize::Small,
+            (_, Some(ImageSizePreference::Medium)) => ImageSize::Medium,
+            (_, Some(ImageSizePreference::Large)) => ImageSize::Large,
+            (Some(ImageOrientation::Square), None) => ImageSize::Large,
+            (_, None) => self.image_config.size,
+        };
+        // every model draws the large square
+        let size = capabilities
+            .size(size)
+            .or(capabilities.size(ImageSize::Large))
+            .ok_or("Image model has no square size")?;
+        let model = image_model.to_string();
+        let request = |n: i64| GenerateImageRequest {
+            model: model.clone(),
+            prompt: prompt.clone(),
+            n,
+            size: size.clone(),
+            response_format: capabilities
+                .b64_json_format
+                .then_some(GenImageResponseFormat::B64Json),
+            quality: options
+                .quality
+                .and_then(|quality| capabilities.quality(quality)),
+            style: options.style.and_then(|style| capabilities.style(style)),
         };
+
+        // more images than the model draws at once are split into requests sent together
+        let count = self.image_count(options);
+        let requests = (0..count)
+            .step_by(capabilities.max_count as usize)
+            .map(|start| request((count - start).min(capabilities.max_count)))
+            .map(|request| self.request_images(request));
+        let images = future::try_join_all(requests)
+            .await?
+            .into_iter()
+            .flat_map(|response| response.data)
+            .map(GeneratedImage::from)
+            .collect();
+
+        Ok(GeneratedImages {
+            images,
+            model,
+            size,
+        })
+    }
+
+    async fn request_images(
+        &self,
+        request: GenerateImageRequest,
+    ) -> Result<GenerateImageResponse, &'static str> {
         let client = reqwest::Client::new();
         let response = client
             .post("https://api.openai.com/v1/images/generations")
@@ -54,132 +189,806 @@ impl Gpt {
             .json(&request)
             .send()
             .await
-            .expect("Failed to send generate image request");
+            .map_err(|_| "Failed to send generate image request")?;
 
-        let response: GenerateImageResponse = response
-            .json()
+        let response = images_response(response).await?;
+        self.record_usage(&request.model, 0, 0, response.data.len());
+
+        Ok(response)
+    }
+
+    // redraws the image following the prompt
+    pub async fn edit_image(
+        &self,
+        image: &domain::Image,
+        mask: Option<&domain::Image>,
+        prompt: String,
+        options: &ImageOptions,
+    ) -> Result<GeneratedImages, &'static str> {
+        let Some(mask) = mask else {
+            // dall-e-2 leaves an opaque image without a mask as it is,
+            // gpt-image-1 redraws all of it keeping what the prompt doesn't change
+            let capabilities = ImageModel::GptImage1.capabilities();
+            let size = capabilities
+                .size(ImageSize::Large)
+                .ok_or("Image model has no square size")?;
+            let mut form = Form::new()
+                .part("image", png_part(image)?)
+                .text("prompt", prompt)
+                .text("model", ImageModel::GptImage1.to_string())
+                .text("n", self.image_count(options).to_string())
+                .text("size", size.clone());
+            if let Some(quality) = options
+                .quality
+                .and_then(|quality| capabilities.quality(quality))
+            {
+                form = form.text("quality", quality);
+            }
+
+            return self
+                .multipart_images(ImageModel::GptImage1, "edits", form, size)
+                .await;
+        };
+
+        // only the transparent areas of the mask are redrawn,
+        // it has to end up with the same dimensions as the image
+        let size = self.square_image_size(options);
+        let form = Form::new()
+            .part("image", png_part(image)?)
+            .part("mask", png_part(mask)?)
+            .text("prompt", prompt)
+            .text("model", ImageModel::DallE2.to_string())
+            .text("n", self.image_count(options).to_string())
+            .text("size", size.clone())
+            .text("response_format", "b64_json");
+
+        self.multipart_images(ImageModel::DallE2, "edits", form, size)
             .await
-            .expect("Failed to parse GenerateImageResponse");
+    }
 
-        let images: Vec<String> = response
-            .data
-            .into_iter()
-            .map(|image| image.b64_json)
-            .collect();
+    pub async fn create_variation(
+        &self,
+        image: &domain::Image,
+        options: &ImageOptions,
+    ) -> Result<GeneratedImages, &'static str> {
+        let size = self.square_image_size(options);
+        let form = Form::new()
+            .part("image", png_part(image)?)
+            .text("model", ImageModel::DallE2.to_string())
+            .text("n", self.image_count(options).to_string())
+            .text("size", size.clone())
+            .text("response_format", "b64_json");
 
-        Ok(images)
+        self.multipart_images(ImageModel::DallE2, "variations", form, size)
+            .await
     }
 
-    pub async fn chat(&self, messages: Vec<domain::Message>) -> Result<String, &'static str> {
-        let messages = messages
-            .into_iter()
-            .map(|message| Message {
-                role: message.from.into(),
-                content: message.text,
-            })
+    // variations are only supported by dall-e-2, edits by dall-e-2 and gpt-image-1
+    async fn multipart_images(
+        &self,
+        model: ImageModel,
+        endpoint: &str,
+        form: Form,
+        size: String,
+    ) -> Result<GeneratedImages, &'static str> {
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("https://api.openai.com/v1/images/{}", endpoint))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|_| "Failed to send image request")?;
+
+        let response = images_response(response).await?;
+        self.record_usage(&model.to_string(), 0, 0, response.data.len());
+
+        Ok(GeneratedImages {
+            images: response
+                .data
+                .into_iter()
+                .map(GeneratedImage::from)
+                .collect(),
+            model: model.to_string(),
+            size,
+        })
+    }
+
+    fn image_count(&self, options: &ImageOptions) -> i64 {
+        options
+            .count
+            .map(i64::from)
+            .unwrap_or(self.image_config.count)
+            .clamp(1, MAX_IMAGE_COUNT)
+    }
+
+    // dall-e-2 edits and variations are square only
+    fn square_image_size(&self, options: &ImageOptions) -> String {
+        let size = match options.size {
+            Some(ImageSizePreference::Small) => ImageSize::Small,
+            Some(ImageSizePreference::Medium) => ImageSize::Medium,
+            Some(ImageSizePreference::Large) => ImageSize::Large,
+            None => self.image_config.size,
+        };
+
+        DALL_E_2
+            .size(size)
+            .unwrap_or(DALL_E_2_SQUARE_SIZE.to_string())
+    }
+
+    pub async fn chat(
+        &self,
+        messages: Vec<domain::Message>,
+        options: &ChatOptions,
+    ) -> Result<String, &'static str> {
+        self.chat_with_tools(
+            messages,
+            options,
+            &ToolRegistry::new(),
+            &ToolContext::default(),
+        )
+        .await
+    }
+
+    // the model may call tools any number of times before it answers
+    pub async fn chat_with_tools(
+        &self,
+        messages: Vec<domain::Message>,
+        options: &ChatOptions,
+        tools: &ToolRegistry,
+        context: &ToolContext,
+    ) -> Result<String, &'static str> {
+        let system_message = Message::new(Role::System, options.system_prompt(&self.prompts));
+        let mut messages = std::iter::once(system_message)
+            .chain(
+                messages
+                    .into_iter()
+                    .map(|message| Message::new(message.from.into(), message.text)),
+            )
             .collect::<Vec<Message>>();
 
-        let request = CompletionsRequest {
-            model: "gpt-4o".to_string(),
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let request = CompletionsRequest {
+                model: self.chat_model.clone(),
+                messages: messages.clone(),
+                temperature: Some(0.7),
+                response_format: None,
+                tools: tools.definitions(context),
+            };
+            let response = self
+                .completions(request)
+                .await
+                .expect("Failed to get chat response");
+
+            let message = response.choices[0].message.clone();
+            if message.tool_calls.is_empty() {
+                return Ok(message.content);
+            }
+
+            // calls of the same round don't depend on each other
+            let results = future::join_all(
+                message
+                    .tool_calls
+                    .iter()
+                    .map(|call| tools.call(&call.function, context)),
+            )
+            .await;
+            let results = message
+                .tool_calls
+                .iter()
+                .zip(results)
+                .map(|(call, result)| Message {
+                    tool_call_id: Some(call.id.clone()),
+                    ..Message::new(Role::Tool, result)
+                })
+                .collect::<Vec<Message>>();
+            messages.push(message);
+            messages.extend(results);
+        }
+
+        Err("Too many tool calls without an answer")
+    }
+
+    // the schema is derived from T, an answer that doesn't parse is asked for once more
+    pub async fn structured<T: DeserializeOwned + schemars::JsonSchema>(
+        &self,
+        model: String,
+        messages: Vec<Message>,
+        temperature: Option<f64>,
+    ) -> Result<T, &'static str> {
+        let name = T::schema_name()
+            .chars()
+            .map(|char| {
+                if char.is_ascii_alphanumeric() {
+                    char
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut request = CompletionsRequest {
+            model,
             messages,
-            temperature: Some(0.7),
-            response_format: None,
+            temperature,
+            response_format: Some(ResponseFormat::new(name, strict_schema::<T>())),
+            tools: None,
+        };
+
+        let content = self.completion_content(request.clone()).await?;
+        let error = match serde_json::from_str(&content) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
         };
+        request.messages.extend([
+            Message::new(Role::Assistant, content),
+            Message::new(
+                Role::User,
+                format!(
+                    "Your reply could not be parsed: {}. Reply again with JSON matching the schema.",
+                    error
+                ),
+            ),
+        ]);
+        let content = self.completion_content(request).await?;
+
+        serde_json::from_str(&content).map_err(|_| "Failed to parse structured output")
+    }
+
+    async fn completion_content(
+        &self,
+        request: CompletionsRequest,
+    ) -> Result<String, &'static str> {
         let response = self
             .completions(request)
             .await
-            .expect("Failed to get chat response");
+            .map_err(|_| "Failed to get completion")?;
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or("Completion returned no choice")?;
 
-        let text = response.choices[0].message.content.clone();
-        Ok(text)
+        Ok(choice.message.content)
     }
 
+    // the latest message is classified, earlier ones only help to understand it
     pub async fn detect_demand(
         &self,
-        chat: String,
-    ) -> Result<(Context, UserDemand), reqwest::Error> {
-        let response_format = ResponseFormat::new(
-            "user_demand".to_string(),
-            json!({
-                "type": "object",
-                "properties": {
-                    "context": {
-                        "type": "string"
-                    },
-                    "user_demand": {
-                        "type": "string",
-                        "enum": ["Chat", "CreateImage"],
-                    },
-               },
-                "required": ["context", "user_demand"],
-                "additionalProperties": false,
-            }),
+        messages: Vec<domain::Message>,
+    ) -> Result<DemandDetection, &'static str> {
+        let (latest, history) = messages.split_last().ok_or("No message to classify")?;
+        let transcript = history
+            .iter()
+            .map(|message| {
+                let from = match message.from {
+                    domain::Actor::User => "User",
+                    domain::Actor::Bot => "Assistant",
+                };
+                format!("{}: {}", from, message_text(message))
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let messages = vec![
+            Message::new(Role::System, self.prompts.detect_demand()),
+            Message::new(
+                Role::User,
+                format!(
+                    "Recent conversation:\n{}\n\nLatest message:\n{}",
+                    transcript,
+                    message_text(latest)
+                ),
+            ),
+        ];
+        let user_demand: schema::UserDemand = self
+            .structured("gpt-4o-mini".to_string(), messages, Some(0.0))
+            .await?;
+
+        let context = Context::new(user_demand.context);
+        let demand = match user_demand.user_demand {
+            UserDemandLabel::Chat => UserDemand::Chat,
+            UserDemandLabel::CreateImage => UserDemand::CreateImage,
+            UserDemandLabel::EditImage => UserDemand::EditImage(
+                user_demand
+                    .image_edits
+                    .iter()
+                    .map(|edit| ImageEdit::try_from(edit.0.as_str()))
+                    .collect::<Result<Vec<ImageEdit>, &'static str>>()?,
+            ),
+            UserDemandLabel::RefineImage => UserDemand::RefineImage,
+            UserDemandLabel::CreateVariation => UserDemand::CreateVariation,
+            UserDemandLabel::DescribeImage => UserDemand::DescribeImage,
+            UserDemandLabel::Translate => UserDemand::Translate,
+            UserDemandLabel::Summarize => UserDemand::Summarize,
+            UserDemandLabel::Reminder => UserDemand::Reminder,
+            UserDemandLabel::Help => UserDemand::Help,
+            UserDemandLabel::Settings => UserDemand::Settings,
+        };
+
+        Ok(DemandDetection {
+            context,
+            demand,
+            confidence: user_demand.confidence.clamp(0.0, 1.0),
+        })
+    }
+
+    // answers the message about the image, e.g. what is in it
+    pub async fn describe_image(
+        &self,
+        image: &domain::Image,
+        question: String,
+        options: &ChatOptions,
+    ) -> Result<String, &'static str> {
+        // the preview budget keeps the request small, details are rarely needed
+        let preview = image.to_preview()?;
+        let url = format!(
+            "data:{};base64,{}",
+            preview.format.mime_type(),
+            STANDARD.encode(&preview.data)
         );
-        let request = CompletionsRequest {
-            model: "gpt-4o-mini".to_string(),
-            messages: vec![
-                Message {
-                    role: Role::System,
-                    content: "You are an expert at detecting user demand. \n\
-                        Describe the user's demand as a short title for context field.\n\
-                        AND Choose the most appropriate label for context from the following options:\n\
-                        - Chat\n\
-                        - CreateImage"
-                        .to_string(),
-                },
-                Message {
+        let system_message = VisionMessage {
+            role: Role::System,
+            content: vec![ContentPart::Text {
+                text: options.system_prompt(&self.prompts),
+            }],
+        };
+        let request = VisionCompletionsRequest {
+            model: self.chat_model.clone(),
+            messages: std::iter::once(system_message)
+                .chain([VisionMessage {
                     role: Role::User,
-                    content: chat,
-                },
-            ],
-            temperature: Some(0.0),
-            response_format: Some(response_format),
+                    content: vec![
+                        ContentPart::ImageUrl {
+                            image_url: ImageUrl { url },
+                        },
+                        ContentPart::Text { text: question },
+                    ],
+                }])
+                .collect(),
+            temperature: Some(0.7),
         };
 
-        let response = self.completions(request).await?;
-        let content = response.choices[0].message.content.clone();
-        let user_demand: schema::UserDemand =
-            serde_json::from_str(&content).expect("Failed to parse user demand");
+        let response: CompletionsResponse = self
+            .post_completions(&request)
+            .await
+            .map_err(|_| "Failed to describe image")?;
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or("Completion returned no choice")?;
 
-        let context = Context::new(user_demand.context);
-        let user_demand =
-            UserDemand::try_from(user_demand.user_demand).expect("Invalid user demand");
+        Ok(choice.message.content)
+    }
+
+    pub async fn extract_reminder(&self, text: String) -> Result<Reminder, &'static str> {
+        let messages = vec![
+            Message::new(
+                Role::System,
+                format!(
+                    "Extract the reminder the user asks for. It is now {}. \
+                    Set minutes_from_now to when the reminder is due, or null if the user doesn't say.",
+                    chrono::Utc::now().to_rfc3339()
+                ),
+            ),
+            Message::new(Role::User, text),
+        ];
 
-        Ok((context, user_demand))
+        self.structured("gpt-4o-mini".to_string(), messages, Some(0.0))
+            .await
+    }
+
+    pub async fn extract_settings(&self, text: String) -> Result<SettingsUpdate, &'static str> {
+        let messages = vec![
+            Message::new(
+                Role::System,
+                format!(
+                    "Extract the settings the user wants to change. \
+                    Leave a setting null unless the user asks to change it. \
+                    persona is one of {} when the user names it, otherwise a short description. \
+                    history_opt_out is true when the user doesn't want their history kept.",
+                    self.prompts.persona_names().join(", ")
+                ),
+            ),
+            Message::new(Role::User, text),
+        ];
+
+        self.structured("gpt-4o-mini".to_string(), messages, Some(0.0))
+            .await
     }
 
     pub async fn create_image_prompt(
         &self,
         messages: Vec<domain::Message>,
-    ) -> Result<String, &'static str> {
-        let system_message = Message {
-            role: Role::System,
-            content: "You are an expert at creating image prompts.
-            You will be given a chat history.
-            You need to create an image prompt mainly for the latest message.
-            But you can also use previous messages to create the prompt.
-            "
-            .to_string(),
-        };
+    ) -> Result<ImagePrompt, &'static str> {
+        let system_message = Message::new(Role::System, self.prompts.create_image_prompt());
         let messages = messages
             .into_iter()
-            .map(|message| Message {
-                role: message.from.into(),
-                content: message.text,
-            })
+            .map(|message| Message::new(message.from.into(), message.text))
             .collect::<Vec<Message>>();
-        let request = CompletionsRequest {
-            model: "gpt-4o-mini".to_string(),
-            messages: vec![vec![system_message], messages].concat(),
-            temperature: Some(0.7),
-            response_format: None,
+        let image_prompt: schema::ImagePrompt = self
+            .structured(
+                "gpt-4o-mini".to_string(),
+                [vec![system_message], messages].concat(),
+                Some(0.7),
+            )
+            .await?;
+
+        Ok(image_prompt.into())
+    }
+}
+
+impl Moderation for Gpt {
+    async fn moderate(&self, text: String) -> Result<ModerationResult, &'static str> {
+        let request = ModerationRequest {
+            model: "omni-moderation-latest".to_string(),
+            input: text,
         };
-        let response = self
-            .completions(request)
+        let client = reqwest::Client::new();
+        let response: ModerationResponse = client
+            .post("https://api.openai.com/v1/moderations")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|_| "Failed to send moderation request")?
+            .json()
             .await
-            .expect("Failed to create image prompt");
-        let text = response.choices[0].message.content.clone();
-        Ok(text)
+            .map_err(|_| "Failed to parse ModerationResponse")?;
+
+        let result = response
+            .results
+            .into_iter()
+            .next()
+            .ok_or("Moderation returned no result")?;
+        let mut categories = result
+            .categories
+            .into_iter()
+            .filter(|(_, flagged)| *flagged)
+            .map(|(category, _)| category)
+            .collect::<Vec<String>>();
+        categories.sort();
+
+        Ok(ModerationResult {
+            flagged: result.flagged,
+            categories,
+        })
+    }
+}
+
+async fn images_response(
+    response: reqwest::Response,
+) -> Result<GenerateImageResponse, &'static str> {
+    if response.status().is_success() {
+        return response
+            .json()
+            .await
+            .map_err(|_| "Failed to parse GenerateImageResponse");
+    }
+
+    let error: ErrorResponse = response
+        .json()
+        .await
+        .map_err(|_| "Failed to parse image error response")?;
+    match error.error.code.as_deref() {
+        Some("content_policy_violation") | Some("moderation_blocked") => {
+            Err(CONTENT_POLICY_VIOLATION)
+        }
+        _ => Err("Image request failed"),
+    }
+}
+
+// schemars output narrowed to what strict structured outputs accept
+pub fn strict_schema<T: schemars::JsonSchema>() -> serde_json::Value {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            // strict mode has no $ref support worth relying on
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+    let mut schema = serde_json::to_value(generator.into_root_schema_for::<T>())
+        .expect("Failed to serialize JSON schema");
+    make_strict(&mut schema);
+
+    schema
+}
+
+fn make_strict(schema: &mut serde_json::Value) {
+    let serde_json::Value::Object(object) = schema else {
+        return;
+    };
+    for keyword in ["title", "format", "minimum", "maximum", "default"] {
+        object.remove(keyword);
+    }
+
+    // every property is required, optional ones are nullable instead
+    if let Some(serde_json::Value::Object(properties)) = object.get_mut("properties") {
+        properties.values_mut().for_each(make_strict);
+        let required = properties
+            .keys()
+            .cloned()
+            .map(serde_json::Value::String)
+            .collect();
+        object.insert("required".to_string(), serde_json::Value::Array(required));
+        object.insert(
+            "additionalProperties".to_string(),
+            serde_json::Value::Bool(false),
+        );
+    }
+    if let Some(items) = object.get_mut("items") {
+        make_strict(items);
+    }
+    if let Some(serde_json::Value::Array(schemas)) = object.get_mut("anyOf") {
+        schemas.iter_mut().for_each(make_strict);
+    }
+
+    // a nullable enum has to list null as well
+    let nullable = matches!(
+        object.get("type"),
+        Some(serde_json::Value::Array(types)) if types.contains(&json!("null"))
+    );
+    if let Some(serde_json::Value::Array(values)) = object.get_mut("enum") {
+        if nullable && !values.contains(&serde_json::Value::Null) {
+            values.push(serde_json::Value::Null);
+        }
+    }
+}
+
+// the demand of the latest message along with how sure the model is of it
+#[derive(Debug, Clone)]
+pub struct DemandDetection {
+    pub context: Context,
+    pub demand: UserDemand,
+    pub confidence: f64,
+}
+
+// what the model reads for a message, images have no text of their own
+fn message_text(message: &domain::Message) -> String {
+    if message.text.is_empty() && message.image.is_some() {
+        "[image]".to_string()
+    } else {
+        message.text.clone()
+    }
+}
+
+impl SettingsUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.image_size.is_none()
+            && self.image_orientation.is_none()
+            && self.image_count.is_none()
+            && self.reply_language.is_none()
+            && self.persona.is_none()
+            && self.verbosity.is_none()
+            && self.history_opt_out.is_none()
+    }
+
+    pub fn apply(self, preferences: &mut UserPreferences) {
+        if let Some(size) = self.image_size {
+            preferences.image_size = Some(match size {
+                SettingsImageSize::Small => ImageSizePreference::Small,
+                SettingsImageSize::Medium => ImageSizePreference::Medium,
+                SettingsImageSize::Large => ImageSizePreference::Large,
+            });
+        }
+        if let Some(orientation) = self.image_orientation {
+            preferences.image_orientation = Some(match orientation {
+                ImagePromptOrientation::Square => ImageOrientation::Square,
+                ImagePromptOrientation::Landscape => ImageOrientation::Landscape,
+                ImagePromptOrientation::Portrait => ImageOrientation::Portrait,
+            });
+        }
+        if let Some(count) = self.image_count {
+            preferences.image_count = Some(count.clamp(1, MAX_IMAGE_COUNT as u8));
+        }
+        if let Some(language) = self.reply_language {
+            preferences.reply_language = Some(language);
+        }
+        if let Some(persona) = self.persona {
+            preferences.persona = Some(persona);
+        }
+        if let Some(verbosity) = self.verbosity {
+            preferences.verbosity = Some(match verbosity {
+                SettingsVerbosity::Concise => Verbosity::Concise,
+                SettingsVerbosity::Normal => Verbosity::Normal,
+                SettingsVerbosity::Detailed => Verbosity::Detailed,
+            });
+        }
+        if let Some(history_opt_out) = self.history_opt_out {
+            preferences.history_opt_out = history_opt_out;
+        }
+    }
+}
+
+// what the chat is asked to do with the message, plain conversation when None
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatTask {
+    Translate,
+    Summarize,
+}
+
+impl ChatTask {
+    fn instruction(&self) -> &'static str {
+        match self {
+            Self::Translate => {
+                "Translate the text in the latest message into the language the user asks for, \
+                or into English if they name none. Reply with the translation only."
+            }
+            Self::Summarize => {
+                "Summarize the text in the latest message, or the conversation so far \
+                if the message has no text of its own, in a few short bullet points."
+            }
+        }
+    }
+}
+
+// per-request chat settings, usually built from the user's preferences
+#[derive(Debug, Clone, Default)]
+pub struct ChatOptions {
+    // display name of the user, told to the model when known
+    pub user_name: Option<String>,
+    pub reply_language: Option<String>,
+    pub persona: Option<String>,
+    pub verbosity: Option<Verbosity>,
+    pub task: Option<ChatTask>,
+}
+
+impl ChatOptions {
+    pub fn with_task(self, task: ChatTask) -> Self {
+        Self {
+            task: Some(task),
+            ..self
+        }
+    }
+
+    pub fn with_user_name(self, user_name: Option<String>) -> Self {
+        Self { user_name, ..self }
+    }
+
+    // the persona and reply language are left to the chat template
+    fn system_prompt(&self, prompts: &Prompts) -> String {
+        [
+            self.task.map(|task| task.instruction().to_string()),
+            Some(prompts.chat(&PromptVariables {
+                user_name: self.user_name.clone(),
+                language: self.reply_language.clone(),
+                persona: self.persona.clone(),
+            })),
+            self.verbosity.map(|verbosity| {
+                match verbosity {
+                    Verbosity::Concise => "Keep your replies short and to the point.",
+                    Verbosity::Normal => "Reply with a moderate amount of detail.",
+                    Verbosity::Detailed => "Reply thoroughly with plenty of detail.",
+                }
+                .to_string()
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join("\n")
+    }
+}
+
+impl From<&UserPreferences> for ChatOptions {
+    fn from(preferences: &UserPreferences) -> Self {
+        Self {
+            user_name: None,
+            reply_language: preferences.reply_language.clone(),
+            persona: preferences.persona.clone(),
+            verbosity: preferences.verbosity,
+            task: None,
+        }
+    }
+}
+
+// the images endpoints take square PNGs under 4 MB
+const EDIT_IMAGE_BUDGET: ImageBudget = ImageBudget {
+    format: EncodeFormat::Png,
+    max_bytes: 4_000_000,
+    max_dimension: 1024,
+};
+
+fn png_part(image: &domain::Image) -> Result<Part, &'static str> {
+    let png = image
+        .edit(&[ImageEdit::CropSquare])?
+        .encode_within(&EDIT_IMAGE_BUDGET)?;
+
+    Part::bytes(png.data)
+        .file_name("image.png")
+        .mime_str("image/png")
+        .map_err(|_| "Invalid image MIME type")
+}
+
+// images along with the settings they were generated with
+#[derive(Debug, Clone)]
+pub struct GeneratedImages {
+    pub images: Vec<GeneratedImage>,
+    pub model: String,
+    pub size: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+    pub b64_json: String,
+    // the prompt the model actually drew, when it rewrote ours
+    pub revised_prompt: Option<String>,
+}
+
+impl From<schema::Image> for GeneratedImage {
+    fn from(image: schema::Image) -> Self {
+        Self {
+            b64_json: image.b64_json,
+            revised_prompt: image.revised_prompt,
+        }
+    }
+}
+
+// per-request image settings, None falls back to the GENERATE_IMAGE_* configuration
+#[derive(Debug, Clone, Default)]
+pub struct ImageOptions {
+    pub size: Option<ImageSizePreference>,
+    pub orientation: Option<ImageOrientation>,
+    pub count: Option<u8>,
+    pub style: Option<ImageStyle>,
+    pub quality: Option<ImageQuality>,
+}
+
+impl ImageOptions {
+    // settings left unset here are taken from the fallback
+    pub fn or(self, fallback: &ImageOptions) -> Self {
+        Self {
+            size: self.size.or(fallback.size),
+            orientation: self.orientation.or(fallback.orientation),
+            count: self.count.or(fallback.count),
+            style: self.style.or(fallback.style),
+            quality: self.quality.or(fallback.quality),
+        }
+    }
+}
+
+impl From<&UserPreferences> for ImageOptions {
+    fn from(preferences: &UserPreferences) -> Self {
+        Self {
+            size: preferences.image_size,
+            orientation: preferences.image_orientation,
+            count: preferences.image_count,
+            style: None,
+            quality: None,
+        }
+    }
+}
+
+// the image prompt along with the options inferred from the user's wording
+#[derive(Debug, Clone)]
+pub struct ImagePrompt {
+    pub prompt: String,
+    pub options: ImageOptions,
+}
+
+impl From<schema::ImagePrompt> for ImagePrompt {
+    fn from(image_prompt: schema::ImagePrompt) -> Self {
+        Self {
+            prompt: image_prompt.prompt,
+            options: ImageOptions {
+                size: None,
+                orientation: image_prompt
+                    .orientation
+                    .map(|orientation| match orientation {
+                        ImagePromptOrientation::Square => ImageOrientation::Square,
+                        ImagePromptOrientation::Landscape => ImageOrientation::Landscape,
+                        ImagePromptOrientation::Portrait => ImageOrientation::Portrait,
+                    }),
+                count: image_prompt.count,
+                style: image_prompt.style.map(|style| match style {
+                    ImagePromptStyle::Vivid => ImageStyle::Vivid,
+                    ImagePromptStyle::Natural => ImageStyle::Natural,
+                }),
+                quality: image_prompt.quality.map(|quality| match quality {
+                    ImagePromptQuality::Standard => ImageQuality::Standard,
+                    ImagePromptQuality::High => ImageQuality::High,
+                }),
+            },
+        }
     }
 }
 
@@ -192,18 +1001,31 @@ struct ImageConfig {
 
 impl ImageConfig {
     fn new() -> Result<Self, &'static str> {
-        let image_model = env::var("GENERATE_IMAGE_MODEL")
-            .expect("Please set the IMAGE_MODEL environment variable")
+        let image_model: ImageModel = env::var("GENERATE_IMAGE_MODEL")
+            .expect("Please set the GENERATE_IMAGE_MODEL environment variable")
             .try_into()?;
 
-        let image_size = env::var("GENERATE_IMAGE_SIZE")
-            .expect("Please set the IMAGE_SIZE environment variable")
+        let image_size: ImageSize = env::var("GENERATE_IMAGE_SIZE")
+            .expect("Please set the GENERATE_IMAGE_SIZE environment variable")
             .try_into()?;
+        if image_model.capabilities().size(image_size).is_none() {
+            return Err(match image_model {
+                ImageModel::DallE2 => {
+                    "dall-e-2 only supports small, medium or large GENERATE_IMAGE_SIZE"
+                }
+                ImageModel::DallE3 | ImageModel::GptImage1 => {
+                    "dall-e-3 and gpt-image-1 only support large, landscape or portrait GENERATE_IMAGE_SIZE"
+                }
+            });
+        }
 
         let image_count = env::var("GENERATE_IMAGE_COUNT")
-            .expect("Please set the N_IMAGE environment variable")
+            .expect("Please set the GENERATE_IMAGE_COUNT environment variable")
             .parse()
-            .expect("Failed to parse N_IMAGE");
+            .map_err(|_| "GENERATE_IMAGE_COUNT must be a number")?;
+        if !(1..=MAX_IMAGE_COUNT).contains(&image_count) {
+            return Err("GENERATE_IMAGE_COUNT must be between 1 and 10");
+        }
 
         Ok(ImageConfig {
             model: image_model,
@@ -213,10 +1035,91 @@ impl ImageConfig {
     }
 }
 
+// the most images a single reply carries, also dall-e-2's limit per request
+const MAX_IMAGE_COUNT: i64 = 10;
+
+// what the images API accepts from each model
+struct ImageCapabilities {
+    // API values of the sizes the model draws
+    sizes: &'static [(ImageSize, &'static str)],
+    // images per request, more are drawn in parallel requests
+    max_count: i64,
+    // API values for standard and high quality, None when the parameter is not taken
+    quality: Option<(&'static str, &'static str)>,
+    // API values for vivid and natural style
+    style: Option<(&'static str, &'static str)>,
+    // whether base64 output has to be asked for
+    b64_json_format: bool,
+}
+
+impl ImageCapabilities {
+    fn size(&self, size: ImageSize) -> Option<String> {
+        self.sizes
+            .iter()
+            .find(|(supported, _)| *supported == size)
+            .map(|(_, value)| value.to_string())
+    }
+
+    fn quality(&self, quality: ImageQuality) -> Option<String> {
+        let (standard, high) = self.quality?;
+        Some(match quality {
+            ImageQuality::Standard => standard.to_string(),
+            ImageQuality::High => high.to_string(),
+        })
+    }
+
+    fn style(&self, style: ImageStyle) -> Option<String> {
+        let (vivid, natural) = self.style?;
+        Some(match style {
+            ImageStyle::Vivid => vivid.to_string(),
+            ImageStyle::Natural => natural.to_string(),
+        })
+    }
+}
+
+const DALL_E_2_SQUARE_SIZE: &str = "1024x1024";
+
+const DALL_E_2: ImageCapabilities = ImageCapabilities {
+    sizes: &[
+        (ImageSize::Small, "256x256"),
+        (ImageSize::Medium, "512x512"),
+        (ImageSize::Large, DALL_E_2_SQUARE_SIZE),
+    ],
+    max_count: 10,
+    quality: None,
+    style: None,
+    b64_json_format: true,
+};
+
+const DALL_E_3: ImageCapabilities = ImageCapabilities {
+    sizes: &[
+        (ImageSize::Large, "1024x1024"),
+        (ImageSize::Landscape, "1792x1024"),
+        (ImageSize::Portrait, "1024x1792"),
+    ],
+    max_count: 1,
+    quality: Some(("standard", "hd")),
+    style: Some(("vivid", "natural")),
+    b64_json_format: true,
+};
+
+const GPT_IMAGE_1: ImageCapabilities = ImageCapabilities {
+    sizes: &[
+        (ImageSize::Large, "1024x1024"),
+        (ImageSize::Landscape, "1536x1024"),
+        (ImageSize::Portrait, "1024x1536"),
+    ],
+    max_count: 10,
+    quality: Some(("medium", "high")),
+    style: None,
+    b64_json_format: false,
+};
+
 #[derive(Clone)]
 enum ImageModel {
     DallE2,
     DallE3,
+    GptImage1,
 }
 
 impl TryInto<ImageModel> for String {
@@ -226,52 +1129,55 @@ impl TryInto<ImageModel> for String {
         match self.as_str() {
             "dall-e-2" => Ok(ImageModel::DallE2),
             "dall-e-3" => Ok(ImageModel::DallE3),
-            _ => Err("Invalid image model"),
+            "gpt-image-1" => Ok(ImageModel::GptImage1),
+            _ => Err("Invalid GENERATE_IMAGE_MODEL, expected dall-e-2, dall-e-3 or gpt-image-1"),
         }
     }
 }
 
-impl ToString for ImageModel {
-    fn to_string(&self) -> String {
+impl ImageModel {
+    fn capabilities(&self) -> &'static ImageCapabilities {
         match self {
-            Self::DallE2 => "dall-e-2".to_string(),
-            Self::DallE3 => "dall-e-3".to_string(),
+            Self::DallE2 => &DALL_E_2,
+            Self::DallE3 => &DALL_E_3,
+            Self::GptImage1 => &GPT_IMAGE_1,
         }
     }
 }
 
-#[derive(Clone)]
+impl fmt::Display for ImageModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DallE2 => write!(f, "dall-e-2"),
+            Self::DallE3 => write!(f, "dall-e-3"),
+            Self::GptImage1 => write!(f, "gpt-image-1"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// the exact dimensions depend on the model, see ImageCapabilities
 enum ImageSize {
     Small,     // 256x256
     Medium,    // 512x512
     Large,     // 1024x1024
-    Landscape, // 1792x1024
-    Portrait,  // 1024x1792
-}
-
-impl ImageSize {
-    fn to_num(&self) -> String {
-        match self {
-            Self::Small => "256x256".to_string(),
-            Self::Medium => "512x512".to_string(),
-            Self::Large => "1024x1024".to_string(),
-            Self::Landscape => "1792x1024".to_string(),
-            Self::Portrait => "1024x1792".to_string(),
-        }
-    }
+    Landscape, // 1792x1024 or 1536x1024
+    Portrait,  // 1024x1792 or 1024x1536
 }
 
 impl TryInto<ImageSize> for String {
     type Error = &'static str;
 
     fn try_into(self) -> Result<ImageSize, Self::Error> {
-        Ok(match self.as_str() {
-            "small" => ImageSize::Small,
-            "medium" => ImageSize::Medium,
-            "large" => ImageSize::Large,
-            "landscape" => ImageSize::Landscape,
-            "portrait" => ImageSize::Portrait,
-            _ => panic!("Invalid image size"),
-        })
+        match self.as_str() {
+            "small" => Ok(ImageSize::Small),
+            "medium" => Ok(ImageSize::Medium),
+            "large" => Ok(ImageSize::Large),
+            "landscape" => Ok(ImageSize::Landscape),
+            "portrait" => Ok(ImageSize::Portrait),
+            _ => Err(
+                "Invalid GENERATE_IMAGE_SIZE, expected small, medium, large, landscape or portrait",
+            ),
+        }
     }
 }
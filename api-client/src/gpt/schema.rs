@@ -1,17 +1,45 @@
+use domain::ImageEdit;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+};
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DefaultOnNull};
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CompletionsRequest {
     pub model: String,
     pub messages: Vec<Message>,
     pub temperature: Option<f64>,
     pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
 }
 
+#[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub role: Role,
+    // null when the model only calls tools
+    #[serde_as(deserialize_as = "DefaultOnNull")]
     pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    // set on tool results, the id of the call they answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn new(role: Role, content: String) -> Self {
+        Message {
+            role,
+            content,
+            tool_calls: vec![],
+            tool_call_id: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -20,6 +48,7 @@ pub enum Role {
     System,
     User,
     Assistant,
+    Tool,
 }
 
 impl From<domain::Actor> for Role {
@@ -57,13 +86,13 @@ pub struct Usage {
     pub total_tokens: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ResponseFormat {
     pub r#type: String,
     pub json_schema: JsonSchema,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JsonSchema {
     pub name: String,
     pub schema: serde_json::Value,
@@ -83,10 +112,143 @@ impl ResponseFormat {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, schemars::JsonSchema)]
 pub struct UserDemand {
     pub context: String,
-    pub user_demand: String,
+    pub user_demand: UserDemandLabel,
+    #[serde(default)]
+    pub image_edits: Vec<ImageEditName>,
+    // 0 to 1, how sure the model is of the label
+    pub confidence: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, schemars::JsonSchema)]
+pub enum UserDemandLabel {
+    Chat,
+    CreateImage,
+    EditImage,
+    RefineImage,
+    CreateVariation,
+    DescribeImage,
+    Translate,
+    Summarize,
+    Reminder,
+    Help,
+    Settings,
+}
+
+// one of the names of ImageEdit::ALL
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct ImageEditName(pub String);
+
+impl schemars::JsonSchema for ImageEditName {
+    fn schema_name() -> String {
+        "ImageEditName".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(ImageEdit::ALL.map(|edit| edit.as_str().into()).to_vec()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, schemars::JsonSchema)]
+pub struct ImagePrompt {
+    pub prompt: String,
+    pub orientation: Option<ImagePromptOrientation>,
+    pub count: Option<u8>,
+    pub style: Option<ImagePromptStyle>,
+    pub quality: Option<ImagePromptQuality>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagePromptOrientation {
+    Square,
+    Landscape,
+    Portrait,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagePromptStyle {
+    Vivid,
+    Natural,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagePromptQuality {
+    Standard,
+    High,
+}
+
+#[derive(Serialize, Deserialize, Debug, schemars::JsonSchema)]
+pub struct Reminder {
+    // what to remind the user of, phrased as the reminder itself
+    pub text: String,
+    // None when the message doesn't say when
+    pub minutes_from_now: Option<u32>,
+}
+
+// None leaves the setting as it is
+#[derive(Serialize, Deserialize, Debug, Default, schemars::JsonSchema)]
+pub struct SettingsUpdate {
+    pub image_size: Option<SettingsImageSize>,
+    pub image_orientation: Option<ImagePromptOrientation>,
+    pub image_count: Option<u8>,
+    pub reply_language: Option<String>,
+    pub persona: Option<String>,
+    pub verbosity: Option<SettingsVerbosity>,
+    pub history_opt_out: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsImageSize {
+    Small,
+    Medium,
+    Large,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsVerbosity {
+    Concise,
+    Normal,
+    Detailed,
+}
+
+// chat completion whose messages carry images, content is a list of parts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VisionCompletionsRequest {
+    pub model: String,
+    pub messages: Vec<VisionMessage>,
+    pub temperature: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VisionMessage {
+    pub role: Role,
+    pub content: Vec<ContentPart>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageUrl {
+    // a data URL works as well as a public one
+    pub url: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -95,7 +257,13 @@ pub struct GenerateImageRequest {
     pub prompt: String,
     pub n: i64,
     pub size: String,
+    // gpt-image-1 always returns base64 and rejects the parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub response_format: Option<GenImageResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -114,4 +282,61 @@ pub struct GenerateImageResponse {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Image {
     pub b64_json: String,
+    // only dall-e-3 rewrites the prompt before drawing
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tool {
+    pub r#type: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolCall {
+    pub id: String,
+    pub r#type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FunctionCall {
+    pub name: String,
+    // JSON encoded, and not guaranteed to match the declared schema
+    pub arguments: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub error: ApiError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiError {
+    pub code: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModerationRequest {
+    pub model: String,
+    pub input: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModerationResponse {
+    pub results: Vec<ModerationResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    pub categories: HashMap<String, bool>,
 }
@@ -1,4 +1,16 @@
+pub mod filesystem;
 pub mod firestore;
 pub mod gcs;
 pub mod gpt;
 pub mod line;
+pub mod memory;
+pub mod message_repo;
+pub mod moderation;
+pub mod object_storage;
+pub mod prompts;
+pub mod s3;
+pub mod sqlite;
+pub mod tools;
+pub mod usage;
+pub mod usage_repo;
+pub mod user_repo;
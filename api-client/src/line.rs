@@ -1,11 +1,15 @@
 pub mod schema;
 
+use chrono::DateTime;
 use domain::{Actor, Message, User};
 use schema::{
     Event, EventType, LoadingStart, Message as LineMessage, PushMessage, ReplyMessage, TextMessage,
     WebhookEvent,
 };
 
+// LINE rejects the whole reply when a text message is longer, counted in UTF-16 units
+pub const MAX_TEXT_LENGTH: usize = 5000;
+
 #[derive(Clone)]
 pub struct Line {
     channel_access_token: String,
@@ -25,34 +29,115 @@ impl Line {
         })
     }
 
-    pub fn get_user_message(&self, payload: WebhookEvent) -> Result<Message, &'static str> {
-        let message_event = payload
-            .events
-            .into_iter()
-            .filter(|event| matches!(event.r#type, EventType::Message))
-            .next()
-            .expect("No message event found");
+    pub fn get_user_message(&self, payload: WebhookEvent) -> Result<Option<Message>, &'static str> {
+        let Some(message_event) = payload.events.into_iter().find(is_user_message) else {
+            return Ok(None);
+        };
 
         // extract message from event
-        let message = self.extract_message(message_event);
+        self.extract_message(message_event)
+    }
+
+    // content of the image the user sent, the message itself has no text
+    pub async fn get_user_image(
+        &self,
+        payload: &WebhookEvent,
+    ) -> Result<Option<Vec<u8>>, &'static str> {
+        let Some(message_id) = payload
+            .events
+            .iter()
+            .find(|event| is_user_message(event))
+            .and_then(|event| match &event.message {
+                Some(schema::Message::Received(received)) if received.r#type == "image" => {
+                    Some(received.id.clone())
+                }
+                _ => None,
+            })
+        else {
+            return Ok(None);
+        };
 
-        Ok(message)
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!(
+                "https://api-data.line.me/v2/bot/message/{}/content",
+                message_id
+            ))
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.channel_access_token),
+            )
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|_| "Failed to get image content from LINE API")?;
+        let content = response
+            .bytes()
+            .await
+            .map_err(|_| "Failed to read image content from LINE API")?;
+
+        Ok(Some(content.to_vec()))
     }
 
-    fn extract_message(&self, event: Event) -> Message {
-        match event.message {
-            schema::Message::Text(TextMessage { text, .. }) => Message {
-                user: User {
-                    id: event.source.user_id,
-                },
-                from: Actor::User,
-                text,
-                reply_token: Some(event.reply_token),
-                context: None,
-                image: None,
-            },
-            schema::Message::Image(_) => unimplemented!(),
+    // None when the user blocked the bot or never added it as a friend
+    pub async fn get_display_name(&self, user_id: &str) -> Result<Option<String>, &'static str> {
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("https://api.line.me/v2/bot/profile/{}", user_id))
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.channel_access_token),
+            )
+            .send()
+            .await
+            .map_err(|_| "Failed to get profile from LINE API")?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
         }
+        let profile: schema::Profile = response
+            .error_for_status()
+            .map_err(|_| "Failed to get profile from LINE API")?
+            .json()
+            .await
+            .map_err(|_| "Failed to read profile from LINE API")?;
+
+        Ok(Some(profile.display_name))
+    }
+
+    pub fn get_unfollowed_user_ids(&self, payload: &WebhookEvent) -> Vec<String> {
+        payload
+            .events
+            .iter()
+            .filter(|event| matches!(event.r#type, EventType::Unfollow))
+            .map(|event| event.source.user_id.clone())
+            .collect()
+    }
+
+    fn extract_message(&self, event: Event) -> Result<Option<Message>, &'static str> {
+        let text = if let Some(postback) = event.postback {
+            postback.data
+        } else {
+            match event.message.ok_or("Message event without message")? {
+                schema::Message::Text(TextMessage { text, .. }) => text,
+                // the content is fetched separately by get_user_image
+                schema::Message::Received(received) if received.r#type == "image" => "".to_string(),
+                // stickers, videos and the like are ignored
+                _ => return Ok(None),
+            }
+        };
+
+        Ok(Some(Message {
+            user: User {
+                id: event.source.user_id,
+            },
+            from: Actor::User,
+            text,
+            reply_token: event.reply_token,
+            context: None,
+            image: None,
+            quick_replies: vec![],
+            timestamp: DateTime::from_timestamp_millis(event.timestamp),
+        }))
     }
 
     pub async fn show_loading(&self) -> Result<(), &'static str> {
@@ -123,3 +208,8 @@ impl Line {
         Ok(response)
     }
 }
+
+// a postback is handled like a message carrying its data as text
+fn is_user_message(event: &Event) -> bool {
+    matches!(event.r#type, EventType::Message | EventType::Postback)
+}
@@ -25,10 +25,14 @@ pub struct WebhookEvent {
 #[serde(rename_all = "camelCase")]
 pub struct Event {
     pub r#type: EventType,
-    pub message: Message,
+    // only message events carry a message
+    pub message: Option<Message>,
+    // only postback events carry a postback
+    pub postback: Option<Postback>,
     pub timestamp: i64,
     pub source: Source,
-    pub reply_token: String,
+    // unfollow events cannot be replied to
+    pub reply_token: Option<String>,
     pub mode: String,
     pub webhook_event_id: String,
     pub delivery_context: DeliveryContext,
@@ -38,6 +42,11 @@ pub struct Event {
 #[serde(rename_all = "camelCase")]
 pub enum EventType {
     Message,
+    Postback,
+    Follow,
+    Unfollow,
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -45,6 +54,8 @@ pub enum EventType {
 pub enum Message {
     Text(TextMessage),
     Image(ImageMessage),
+    // messages sent by users other than text only carry an ID to download the content with
+    Received(ReceivedMessage),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,15 +65,26 @@ pub struct TextMessage {
     pub r#type: String,
     pub text: String,
     pub quote_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quick_reply: Option<QuickReply>,
 }
 
 impl From<domain::Message> for Message {
     fn from(message: domain::Message) -> Self {
+        let quick_reply = (!message.quick_replies.is_empty()).then(|| QuickReply {
+            items: message
+                .quick_replies
+                .into_iter()
+                .map(QuickReplyItem::from)
+                .collect(),
+        });
+
         if let Some(image) = message.image {
             Self::Image(ImageMessage {
                 r#type: "image".to_string(),
                 original_content_url: image.url,
                 preview_image_url: image.preview_url,
+                quick_reply,
             })
         } else {
             Self::Text(TextMessage {
@@ -70,6 +92,7 @@ impl From<domain::Message> for Message {
                 r#type: "text".to_string(),
                 text: message.text,
                 quote_token: None,
+                quick_reply,
             })
         }
     }
@@ -81,6 +104,60 @@ pub struct ImageMessage {
     pub r#type: String,
     pub original_content_url: String,
     pub preview_image_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quick_reply: Option<QuickReply>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceivedMessage {
+    pub id: String,
+    pub r#type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickReply {
+    pub items: Vec<QuickReplyItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickReplyItem {
+    pub r#type: String,
+    pub action: PostbackAction,
+}
+
+impl From<domain::QuickReply> for QuickReplyItem {
+    fn from(quick_reply: domain::QuickReply) -> Self {
+        Self {
+            r#type: "action".to_string(),
+            action: PostbackAction {
+                r#type: "postback".to_string(),
+                // the label is also shown in the chat as if the user had sent it
+                display_text: Some(quick_reply.label.clone()),
+                label: quick_reply.label,
+                data: quick_reply.data,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostbackAction {
+    pub r#type: String,
+    // at most 20 characters
+    pub label: String,
+    // at most 300 characters
+    pub data: String,
+    pub display_text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Postback {
+    pub data: String,
 }
 
 impl Message {
@@ -90,6 +167,7 @@ impl Message {
             r#type: "text".to_string(),
             text,
             quote_token,
+            quick_reply: None,
         })
     }
 
@@ -98,6 +176,7 @@ impl Message {
             r#type: "image".to_string(),
             original_content_url,
             preview_image_url,
+            quick_reply: None,
         })
     }
 }
@@ -121,3 +200,9 @@ pub struct LoadingStart {
     pub chat_id: String,
     pub loading_seconds: i64,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub display_name: String,
+}
@@ -0,0 +1,333 @@
+use crate::message_repo::{into_page, MessageKey};
+use chrono::prelude::*;
+use domain::{
+    ContextId, Message, MessagePage, MessageQuery, MessageRepo, OrderDirection, UsageQuery,
+    UsageRecord, UsageRepo, UserPreferences, UserRepo,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct MessageRepoImpl {
+    messages: Arc<Mutex<Vec<StoredMessage>>>,
+}
+
+impl MessageRepoImpl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn list_by<F>(
+        &self,
+        filter: F,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str>
+    where
+        F: Fn(&Message) -> bool,
+    {
+        let stored = self
+            .messages
+            .lock()
+            .map_err(|_| "Message store is poisoned")?;
+
+        // messages are kept in insertion order, so the latest ones are at the end
+        let mut messages = stored
+            .iter()
+            .rev()
+            .filter(|stored| filter(&stored.message))
+            .take(limit as usize)
+            .map(|stored| stored.message.clone())
+            .collect::<Vec<Message>>();
+
+        if let OrderDirection::Ascending = order_direction {
+            messages.reverse();
+        }
+
+        Ok(messages)
+    }
+
+    fn delete_by<F>(&self, filter: F) -> Result<u64, &'static str>
+    where
+        F: Fn(&Message) -> bool,
+    {
+        let mut stored = self
+            .messages
+            .lock()
+            .map_err(|_| "Message store is poisoned")?;
+
+        let count = stored.len();
+        stored.retain(|stored| !filter(&stored.message));
+
+        Ok((count - stored.len()) as u64)
+    }
+}
+
+impl MessageRepo for MessageRepoImpl {
+    async fn save(&self, messages: Vec<Message>) -> Result<(), &'static str> {
+        let Some(user_id) = messages.first().map(|message| message.user.id.clone()) else {
+            return Ok(());
+        };
+        if messages.iter().any(|message| message.user.id != user_id) {
+            return Err("Messages must belong to the same user");
+        }
+        if messages.iter().any(|message| message.context.is_none()) {
+            return Err("Context is required");
+        }
+
+        let mut stored = self
+            .messages
+            .lock()
+            .map_err(|_| "Message store is poisoned")?;
+        let last_sequence = stored
+            .iter()
+            .filter(|stored| stored.message.user.id == user_id)
+            .map(|stored| stored.sequence)
+            .max()
+            .unwrap_or(0);
+
+        let saved_time = Utc::now();
+        stored.extend(
+            messages
+                .into_iter()
+                .zip(last_sequence + 1..)
+                .map(|(message, sequence)| StoredMessage {
+                    message: Message {
+                        reply_token: None,
+                        quick_replies: vec![],
+                        timestamp: Some(message.timestamp.unwrap_or(saved_time)),
+                        ..message
+                    },
+                    sequence,
+                }),
+        );
+
+        Ok(())
+    }
+
+    async fn list_by_user_id(
+        &self,
+        user_id: String,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        self.list_by(|message| message.user.id == user_id, limit, order_direction)
+    }
+
+    async fn list_by_context_id(
+        &self,
+        context_id: ContextId,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        self.list_by(
+            |message| {
+                message
+                    .context
+                    .as_ref()
+                    .is_some_and(|context| context.id == context_id)
+            },
+            limit,
+            order_direction,
+        )
+    }
+
+    async fn query(&self, query: MessageQuery) -> Result<MessagePage, &'static str> {
+        let cursor = query
+            .page_token
+            .as_deref()
+            .map(MessageKey::from_page_token)
+            .transpose()?;
+
+        let stored = self
+            .messages
+            .lock()
+            .map_err(|_| "Message store is poisoned")?;
+
+        let mut messages = stored
+            .iter()
+            .filter(|stored| stored.matches(&query))
+            .map(|stored| (stored.key(), stored.message.clone()))
+            .collect::<Vec<(MessageKey, Message)>>();
+        messages.sort_by(|(a, _), (b, _)| match query.order_direction {
+            OrderDirection::Ascending => a.cmp(b),
+            OrderDirection::Descending => b.cmp(a),
+        });
+
+        let messages = messages
+            .into_iter()
+            .filter(|(key, _)| match (&cursor, query.order_direction) {
+                (None, _) => true,
+                (Some(cursor), OrderDirection::Ascending) => key > cursor,
+                (Some(cursor), OrderDirection::Descending) => key < cursor,
+            })
+            .take(query.limit as usize + 1)
+            .map(|(key, message)| StoredMessage {
+                sequence: key.sequence,
+                message,
+            })
+            .collect::<Vec<StoredMessage>>();
+
+        into_page(messages, query.limit, StoredMessage::key)
+    }
+
+    async fn count(&self, query: MessageQuery) -> Result<u64, &'static str> {
+        let stored = self
+            .messages
+            .lock()
+            .map_err(|_| "Message store is poisoned")?;
+
+        Ok(stored
+            .iter()
+            .filter(|stored| stored.matches(&query))
+            .count() as u64)
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        self.delete_by(|message| message.user.id == user_id)
+    }
+
+    async fn delete_created_before(&self, until: DateTime<Utc>) -> Result<u64, &'static str> {
+        self.delete_by(|message| message.timestamp.unwrap_or_default() < until)
+    }
+}
+
+#[derive(Debug)]
+struct StoredMessage {
+    message: Message,
+    sequence: i64,
+}
+
+impl StoredMessage {
+    fn key(&self) -> MessageKey {
+        MessageKey {
+            created_time: self.message.timestamp.unwrap_or_default(),
+            user_id: self.message.user.id.clone(),
+            sequence: self.sequence,
+        }
+    }
+
+    fn matches(&self, query: &MessageQuery) -> bool {
+        let message = &self.message;
+        let timestamp = message.timestamp.unwrap_or_default();
+
+        query
+            .user_id
+            .as_ref()
+            .is_none_or(|user_id| &message.user.id == user_id)
+            && query.context_id.as_ref().is_none_or(|context_id| {
+                message
+                    .context
+                    .as_ref()
+                    .is_some_and(|context| &context.id == context_id)
+            })
+            && query.from.is_none_or(|from| message.from == from)
+            && query.since.is_none_or(|since| timestamp >= since)
+            && query.until.is_none_or(|until| timestamp < until)
+            && query
+                .has_image
+                .is_none_or(|has_image| message.image.is_some() == has_image)
+    }
+}
+
+impl TryFrom<StoredMessage> for Message {
+    type Error = &'static str;
+
+    fn try_from(stored: StoredMessage) -> Result<Self, Self::Error> {
+        Ok(stored.message)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UsageRepoImpl {
+    records: Arc<Mutex<Vec<UsageRecord>>>,
+}
+
+impl UsageRepoImpl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl UsageRepo for UsageRepoImpl {
+    async fn save(&self, records: Vec<UsageRecord>) -> Result<(), &'static str> {
+        self.records
+            .lock()
+            .map_err(|_| "Usage store is poisoned")?
+            .extend(records);
+
+        Ok(())
+    }
+
+    async fn query(&self, query: UsageQuery) -> Result<Vec<UsageRecord>, &'static str> {
+        let records = self.records.lock().map_err(|_| "Usage store is poisoned")?;
+
+        let mut records = records
+            .iter()
+            .filter(|record| {
+                query
+                    .user_id
+                    .as_ref()
+                    .is_none_or(|user_id| &record.user_id == user_id)
+                    && query.since.is_none_or(|since| record.created_time >= since)
+                    && query.until.is_none_or(|until| record.created_time < until)
+            })
+            .cloned()
+            .collect::<Vec<UsageRecord>>();
+        records.sort_by_key(|record| record.created_time);
+
+        Ok(records)
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        let mut records = self.records.lock().map_err(|_| "Usage store is poisoned")?;
+
+        let count = records.len();
+        records.retain(|record| record.user_id != user_id);
+
+        Ok((count - records.len()) as u64)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UserRepoImpl {
+    users: Arc<Mutex<HashMap<String, UserPreferences>>>,
+}
+
+impl UserRepoImpl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl UserRepo for UserRepoImpl {
+    async fn get_preferences(&self, user_id: String) -> Result<UserPreferences, &'static str> {
+        let users = self.users.lock().map_err(|_| "User store is poisoned")?;
+
+        Ok(users.get(&user_id).cloned().unwrap_or_default())
+    }
+
+    async fn save_preferences(
+        &self,
+        user_id: String,
+        preferences: UserPreferences,
+    ) -> Result<(), &'static str> {
+        self.users
+            .lock()
+            .map_err(|_| "User store is poisoned")?
+            .insert(user_id, preferences);
+
+        Ok(())
+    }
+
+    async fn delete_preferences(&self, user_id: String) -> Result<(), &'static str> {
+        self.users
+            .lock()
+            .map_err(|_| "User store is poisoned")?
+            .remove(&user_id);
+
+        Ok(())
+    }
+}
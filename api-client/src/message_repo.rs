@@ -0,0 +1,151 @@
+use crate::{firestore, memory, sqlite};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::prelude::*;
+use domain::{ContextId, Message, MessagePage, MessageQuery, MessageRepo, OrderDirection};
+use serde::{Deserialize, Serialize};
+
+// message repository backend selected by the MESSAGE_REPO environment variable
+#[derive(Clone, Debug)]
+pub enum MessageRepoImpl {
+    Firestore(firestore::MessageRepoImpl),
+    Sqlite(sqlite::MessageRepoImpl),
+    Memory(memory::MessageRepoImpl),
+}
+
+impl MessageRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let backend = std::env::var("MESSAGE_REPO").unwrap_or("firestore".to_string());
+
+        match backend.as_str() {
+            "firestore" => Ok(Self::Firestore(firestore::MessageRepoImpl::new().await?)),
+            "sqlite" => Ok(Self::Sqlite(sqlite::MessageRepoImpl::new().await?)),
+            "memory" => Ok(Self::Memory(memory::MessageRepoImpl::new())),
+            _ => Err("Invalid MESSAGE_REPO, expected one of firestore, sqlite or memory"),
+        }
+    }
+}
+
+impl MessageRepo for MessageRepoImpl {
+    async fn save(&self, messages: Vec<Message>) -> Result<(), &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.save(messages).await,
+            Self::Sqlite(repo) => repo.save(messages).await,
+            Self::Memory(repo) => repo.save(messages).await,
+        }
+    }
+
+    async fn list_by_user_id(
+        &self,
+        user_id: String,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.list_by_user_id(user_id, limit, order_direction).await,
+            Self::Sqlite(repo) => repo.list_by_user_id(user_id, limit, order_direction).await,
+            Self::Memory(repo) => repo.list_by_user_id(user_id, limit, order_direction).await,
+        }
+    }
+
+    async fn list_by_context_id(
+        &self,
+        context_id: ContextId,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        match self {
+            Self::Firestore(repo) => {
+                repo.list_by_context_id(context_id, limit, order_direction)
+                    .await
+            }
+            Self::Sqlite(repo) => {
+                repo.list_by_context_id(context_id, limit, order_direction)
+                    .await
+            }
+            Self::Memory(repo) => {
+                repo.list_by_context_id(context_id, limit, order_direction)
+                    .await
+            }
+        }
+    }
+
+    async fn query(&self, query: MessageQuery) -> Result<MessagePage, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.query(query).await,
+            Self::Sqlite(repo) => repo.query(query).await,
+            Self::Memory(repo) => repo.query(query).await,
+        }
+    }
+
+    async fn count(&self, query: MessageQuery) -> Result<u64, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.count(query).await,
+            Self::Sqlite(repo) => repo.count(query).await,
+            Self::Memory(repo) => repo.count(query).await,
+        }
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.delete_by_user_id(user_id).await,
+            Self::Sqlite(repo) => repo.delete_by_user_id(user_id).await,
+            Self::Memory(repo) => repo.delete_by_user_id(user_id).await,
+        }
+    }
+
+    async fn delete_created_before(&self, until: DateTime<Utc>) -> Result<u64, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.delete_created_before(until).await,
+            Self::Sqlite(repo) => repo.delete_created_before(until).await,
+            Self::Memory(repo) => repo.delete_created_before(until).await,
+        }
+    }
+}
+
+// position of a message in query order, the cursor behind a page token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct MessageKey {
+    pub created_time: DateTime<Utc>,
+    pub user_id: String,
+    pub sequence: i64,
+}
+
+impl MessageKey {
+    pub fn from_page_token(page_token: &str) -> Result<Self, &'static str> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(page_token)
+            .map_err(|_| "Invalid page token")?;
+
+        serde_json::from_slice(&bytes).map_err(|_| "Invalid page token")
+    }
+
+    pub fn to_page_token(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("Failed to serialize page token"))
+    }
+}
+
+// items are fetched with one extra beyond the limit to tell whether another page exists
+pub(crate) fn into_page<T, K>(
+    mut items: Vec<T>,
+    limit: u32,
+    key: K,
+) -> Result<MessagePage, &'static str>
+where
+    T: TryInto<Message, Error = &'static str>,
+    K: Fn(&T) -> MessageKey,
+{
+    let next_page_token = if items.len() > limit as usize {
+        items.truncate(limit as usize);
+        items.last().map(|item| key(item).to_page_token())
+    } else {
+        None
+    };
+
+    Ok(MessagePage {
+        messages: items
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<Vec<Message>, &'static str>>()?,
+        next_page_token,
+    })
+}
@@ -0,0 +1,79 @@
+use crate::gpt::Gpt;
+use domain::{Moderation, ModerationResult};
+
+// moderation provider selected by the MODERATION environment variable
+#[derive(Clone)]
+pub enum ModerationImpl {
+    OpenAi(Box<Gpt>),
+    Keywords(KeywordModeration),
+    Disabled,
+}
+
+impl ModerationImpl {
+    pub fn new() -> Result<Self, &'static str> {
+        let provider = std::env::var("MODERATION").unwrap_or("openai".to_string());
+
+        match provider.as_str() {
+            "openai" => Ok(Self::OpenAi(Box::new(Gpt::new()?))),
+            "keywords" => Ok(Self::Keywords(KeywordModeration::new()?)),
+            "none" => Ok(Self::Disabled),
+            _ => Err("Invalid MODERATION, expected one of openai, keywords or none"),
+        }
+    }
+}
+
+impl Moderation for ModerationImpl {
+    async fn moderate(&self, text: String) -> Result<ModerationResult, &'static str> {
+        match self {
+            Self::OpenAi(moderation) => moderation.moderate(text).await,
+            Self::Keywords(moderation) => moderation.moderate(text).await,
+            Self::Disabled => Ok(ModerationResult::default()),
+        }
+    }
+}
+
+// flags text containing any listed keyword, works offline
+#[derive(Clone, Debug)]
+pub struct KeywordModeration {
+    keywords: Vec<String>,
+}
+
+impl KeywordModeration {
+    pub fn new() -> Result<Self, &'static str> {
+        let path = std::env::var("MODERATION_KEYWORDS_FILE")
+            .expect("Please set the MODERATION_KEYWORDS_FILE environment variable");
+        let list =
+            std::fs::read_to_string(path).map_err(|_| "Failed to read MODERATION_KEYWORDS_FILE")?;
+
+        Ok(Self::from_list(&list))
+    }
+
+    // one keyword per line, blank lines and lines starting with # are skipped
+    pub fn from_list(list: &str) -> Self {
+        let keywords = list
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|keyword| keyword.to_lowercase())
+            .collect();
+
+        Self { keywords }
+    }
+}
+
+impl Moderation for KeywordModeration {
+    async fn moderate(&self, text: String) -> Result<ModerationResult, &'static str> {
+        let text = text.to_lowercase();
+        let categories = self
+            .keywords
+            .iter()
+            .filter(|keyword| text.contains(keyword.as_str()))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        Ok(ModerationResult {
+            flagged: !categories.is_empty(),
+            categories,
+        })
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{filesystem::FileSystem, gcs::Gcs, s3::S3};
+use domain::{ObjectStorage, StoredObject};
+use std::time::Duration;
+
+// how long the signed URLs of GCS and S3 can be downloaded from
+pub const SIGNED_URL_EXPIRATION: Duration = Duration::from_secs(600);
+
+// object storage backend selected by the OBJECT_STORAGE environment variable
+#[derive(Clone)]
+pub enum ObjectStorageImpl {
+    Gcs(Box<Gcs>),
+    FileSystem(FileSystem),
+    S3(S3),
+}
+
+impl ObjectStorageImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let backend = std::env::var("OBJECT_STORAGE").unwrap_or("gcs".to_string());
+
+        match backend.as_str() {
+            "gcs" => Ok(Self::Gcs(Box::new(Gcs::new().await?))),
+            "local" => Ok(Self::FileSystem(FileSystem::new()?)),
+            "s3" => Ok(Self::S3(S3::new()?)),
+            _ => Err("Invalid OBJECT_STORAGE, expected one of gcs, local or s3"),
+        }
+    }
+
+    // None when the URLs never expire, as the local storage route is not signed
+    pub fn url_expiration(&self) -> Option<Duration> {
+        match self {
+            Self::Gcs(_) | Self::S3(_) => Some(SIGNED_URL_EXPIRATION),
+            Self::FileSystem(_) => None,
+        }
+    }
+}
+
+impl ObjectStorage for ObjectStorageImpl {
+    async fn put(
+        &self,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+    ) -> Result<StoredObject, &'static str> {
+        match self {
+            Self::Gcs(storage) => storage.put(name, content_type, data).await,
+            Self::FileSystem(storage) => storage.put(name, content_type, data).await,
+            Self::S3(storage) => storage.put(name, content_type, data).await,
+        }
+    }
+
+    async fn get(&self, name: String) -> Result<Vec<u8>, &'static str> {
+        match self {
+            Self::Gcs(storage) => storage.get(name).await,
+            Self::FileSystem(storage) => storage.get(name).await,
+            Self::S3(storage) => storage.get(name).await,
+        }
+    }
+
+    async fn stat(&self, name: String) -> Result<Option<StoredObject>, &'static str> {
+        match self {
+            Self::Gcs(storage) => storage.stat(name).await,
+            Self::FileSystem(storage) => storage.stat(name).await,
+            Self::S3(storage) => storage.stat(name).await,
+        }
+    }
+
+    async fn delete(&self, name: String) -> Result<(), &'static str> {
+        match self {
+            Self::Gcs(storage) => storage.delete(name).await,
+            Self::FileSystem(storage) => storage.delete(name).await,
+            Self::S3(storage) => storage.delete(name).await,
+        }
+    }
+
+    async fn list(&self, prefix: Option<String>) -> Result<Vec<StoredObject>, &'static str> {
+        match self {
+            Self::Gcs(storage) => storage.list(prefix).await,
+            Self::FileSystem(storage) => storage.list(prefix).await,
+            Self::S3(storage) => storage.list(prefix).await,
+        }
+    }
+
+    async fn url(&self, name: String) -> Result<String, &'static str> {
+        match self {
+            Self::Gcs(storage) => storage.url(name).await,
+            Self::FileSystem(storage) => storage.url(name).await,
+            Self::S3(storage) => storage.url(name).await,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::Utc;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+// built in, a file of the same name in PROMPTS_DIR replaces each of them
+const CHAT: (&str, &str) = ("chat.txt", include_str!("../prompts/chat.txt"));
+const DETECT_DEMAND: (&str, &str) = (
+    "detect_demand.txt",
+    include_str!("../prompts/detect_demand.txt"),
+);
+const CREATE_IMAGE_PROMPT: (&str, &str) = (
+    "create_image_prompt.txt",
+    include_str!("../prompts/create_image_prompt.txt"),
+);
+// named after their file in the personas directory, more can be added there
+const PERSONAS: [(&str, &str); 2] = [
+    (
+        "assistant",
+        include_str!("../prompts/personas/assistant.txt"),
+    ),
+    ("friend", include_str!("../prompts/personas/friend.txt")),
+];
+const DEFAULT_PERSONA: &str = "assistant";
+
+#[derive(Debug, Clone)]
+struct Templates {
+    chat: String,
+    detect_demand: String,
+    create_image_prompt: String,
+    personas: BTreeMap<String, String>,
+}
+
+impl Templates {
+    fn load(dir: Option<&Path>) -> Result<Self, &'static str> {
+        let template = |(name, builtin): (&str, &str)| match dir {
+            Some(dir) if dir.join(name).exists() => {
+                fs::read_to_string(dir.join(name)).map_err(|_| "Failed to read prompt template")
+            }
+            _ => Ok(builtin.to_string()),
+        };
+
+        let mut personas = PERSONAS
+            .iter()
+            .map(|(name, persona)| (name.to_string(), persona.to_string()))
+            .collect::<BTreeMap<String, String>>();
+        if let Some(entries) = dir.and_then(|dir| fs::read_dir(dir.join("personas")).ok()) {
+            for entry in entries {
+                let path = entry.map_err(|_| "Failed to read personas")?.path();
+                if path.extension().is_some_and(|extension| extension == "txt") {
+                    let name = path
+                        .file_stem()
+                        .and_then(|name| name.to_str())
+                        .ok_or("Invalid persona file name")?
+                        .to_lowercase();
+                    let persona =
+                        fs::read_to_string(&path).map_err(|_| "Failed to read persona")?;
+                    personas.insert(name, persona);
+                }
+            }
+        }
+
+        Ok(Self {
+            chat: template(CHAT)?,
+            detect_demand: template(DETECT_DEMAND)?,
+            create_image_prompt: template(CREATE_IMAGE_PROMPT)?,
+            personas,
+        })
+    }
+}
+
+// what {{user_name}}, {{language}}, {{date}} and {{persona}} are replaced with
+#[derive(Debug, Clone, Default)]
+pub struct PromptVariables {
+    pub user_name: Option<String>,
+    pub language: Option<String>,
+    // a persona name, or a description of one
+    pub persona: Option<String>,
+}
+
+// system prompts, shared by every clone so a reload reaches all of them
+#[derive(Debug, Clone)]
+pub struct Prompts {
+    dir: Option<PathBuf>,
+    default_persona: String,
+    templates: Arc<RwLock<Templates>>,
+}
+
+impl Prompts {
+    pub fn new() -> Result<Self, &'static str> {
+        let dir = env::var("PROMPTS_DIR").ok().map(PathBuf::from);
+        if dir.as_ref().is_some_and(|dir| !dir.is_dir()) {
+            return Err("PROMPTS_DIR is not a directory");
+        }
+        let default_persona = env::var("DEFAULT_PERSONA").unwrap_or(DEFAULT_PERSONA.to_string());
+        let templates = Templates::load(dir.as_deref())?;
+
+        Ok(Self {
+            dir,
+            default_persona,
+            templates: Arc::new(RwLock::new(templates)),
+        })
+    }
+
+    // picks up edited files, the previous templates are kept when any fails to load
+    pub fn reload(&self) -> Result<(), &'static str> {
+        let templates = Templates::load(self.dir.as_deref())?;
+        *self
+            .templates
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = templates;
+
+        Ok(())
+    }
+
+    pub fn chat(&self, variables: &PromptVariables) -> String {
+        self.render(|templates| &templates.chat, variables)
+    }
+
+    pub fn detect_demand(&self) -> String {
+        self.render(
+            |templates| &templates.detect_demand,
+            &PromptVariables::default(),
+        )
+    }
+
+    pub fn create_image_prompt(&self) -> String {
+        self.render(
+            |templates| &templates.create_image_prompt,
+            &PromptVariables::default(),
+        )
+    }
+
+    pub fn persona_names(&self) -> Vec<String> {
+        self.read().personas.keys().cloned().collect()
+    }
+
+    // names of known personas are looked up, anything else is taken as a description
+    fn persona(&self, persona: Option<&str>) -> String {
+        let persona = persona.unwrap_or(&self.default_persona);
+        self.read()
+            .personas
+            .get(&persona.to_lowercase())
+            .map(|persona| persona.trim().to_string())
+            .unwrap_or(format!("Act as the following persona: {}", persona))
+    }
+
+    fn render(
+        &self,
+        template: impl Fn(&Templates) -> &String,
+        variables: &PromptVariables,
+    ) -> String {
+        let template = template(&self.read()).clone();
+
+        template
+            .replace(
+                "{{user_name}}",
+                variables.user_name.as_deref().unwrap_or("the user"),
+            )
+            .replace(
+                "{{language}}",
+                variables
+                    .language
+                    .as_deref()
+                    .unwrap_or("the language of the user's latest message"),
+            )
+            .replace("{{date}}", &Utc::now().format("%Y-%m-%d (%A)").to_string())
+            .replace("{{persona}}", &self.persona(variables.persona.as_deref()))
+            .trim()
+            .to_string()
+    }
+
+    // templates are swapped whole, so a poisoned lock still holds valid ones
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Templates> {
+        self.templates
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::object_storage::SIGNED_URL_EXPIRATION;
+use chrono::prelude::*;
+use domain::{ObjectStorage, StoredObject};
+use s3::{creds::Credentials, error::S3Error, Bucket, Region};
+
+// any S3-compatible store, e.g. AWS S3 or MinIO
+#[derive(Clone, Debug)]
+pub struct S3 {
+    bucket: Box<Bucket>,
+}
+
+impl S3 {
+    pub fn new() -> Result<Self, &'static str> {
+        let bucket =
+            std::env::var("S3_BUCKET").expect("Please set the S3_BUCKET environment variable");
+        let endpoint =
+            std::env::var("S3_ENDPOINT").expect("Please set the S3_ENDPOINT environment variable");
+        let region = std::env::var("S3_REGION").unwrap_or("us-east-1".to_string());
+        let access_key_id = std::env::var("S3_ACCESS_KEY_ID")
+            .expect("Please set the S3_ACCESS_KEY_ID environment variable");
+        let secret_access_key = std::env::var("S3_SECRET_ACCESS_KEY")
+            .expect("Please set the S3_SECRET_ACCESS_KEY environment variable");
+
+        let credentials = Credentials::new(
+            Some(&access_key_id),
+            Some(&secret_access_key),
+            None,
+            None,
+            None,
+        )
+        .map_err(|_| "Invalid S3 credentials")?;
+        let bucket = Bucket::new(&bucket, Region::Custom { region, endpoint }, credentials)
+            .map_err(|_| "Failed to initialize S3 bucket")?
+            // MinIO and most self-hosted stores don't support virtual-hosted buckets
+            .with_path_style();
+
+        Ok(Self { bucket })
+    }
+}
+
+impl ObjectStorage for S3 {
+    async fn put(
+        &self,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+    ) -> Result<StoredObject, &'static str> {
+        self.bucket
+            .put_object_with_content_type(&name, &data, &content_type)
+            .await
+            .map_err(|_| "Failed to upload object")?;
+
+        Ok(StoredObject {
+            name,
+            size: data.len() as u64,
+            created_time: Some(Utc::now()),
+        })
+    }
+
+    async fn get(&self, name: String) -> Result<Vec<u8>, &'static str> {
+        let response = self
+            .bucket
+            .get_object(&name)
+            .await
+            .map_err(|_| "Failed to download object")?;
+
+        Ok(response.to_vec())
+    }
+
+    async fn stat(&self, name: String) -> Result<Option<StoredObject>, &'static str> {
+        match self.bucket.head_object(&name).await {
+            Ok((head, _)) => Ok(Some(StoredObject {
+                size: head.content_length.unwrap_or_default() as u64,
+                created_time: head
+                    .last_modified
+                    .and_then(|time| DateTime::parse_from_rfc2822(&time).ok())
+                    .map(|time| time.with_timezone(&Utc)),
+                name,
+            })),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Err(_) => Err("Failed to get object metadata"),
+        }
+    }
+
+    async fn delete(&self, name: String) -> Result<(), &'static str> {
+        self.bucket
+            .delete_object(&name)
+            .await
+            .map_err(|_| "Failed to delete object")?;
+
+        Ok(())
+    }
+
+    async fn list(&self, prefix: Option<String>) -> Result<Vec<StoredObject>, &'static str> {
+        let results = self
+            .bucket
+            .list(prefix.unwrap_or_default(), None)
+            .await
+            .map_err(|_| "Failed to list objects")?;
+
+        Ok(results
+            .into_iter()
+            .flat_map(|result| result.contents)
+            .map(|object| StoredObject {
+                created_time: DateTime::parse_from_rfc3339(&object.last_modified)
+                    .ok()
+                    .map(|time| time.with_timezone(&Utc)),
+                name: object.key,
+                size: object.size,
+            })
+            .collect())
+    }
+
+    async fn url(&self, name: String) -> Result<String, &'static str> {
+        self.bucket
+            .presign_get(&name, SIGNED_URL_EXPIRATION.as_secs() as u32, None)
+            .await
+            .map_err(|_| "Failed to get presigned URL")
+    }
+}
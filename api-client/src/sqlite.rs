@@ -0,0 +1,618 @@
+use crate::message_repo::{into_page, MessageKey};
+use chrono::prelude::*;
+use domain::{
+    Actor, Context, ContextId, ImageMessage, ImageOrientation, ImageSizePreference, Message,
+    MessagePage, MessageQuery, MessageRepo, OrderDirection, UsageQuery, UsageRecord, UsageRepo,
+    User, UserPreferences, UserRepo, Verbosity,
+};
+use sqlx::{
+    sqlite::{Sqlite, SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+    FromRow, QueryBuilder,
+};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct MessageRepoImpl {
+    pool: SqlitePool,
+}
+
+async fn connect(database_url: &str) -> Result<SqlitePool, &'static str> {
+    let options = SqliteConnectOptions::from_str(database_url)
+        .map_err(|_| "Invalid SQLite database URL")?
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .connect_with(options)
+        .await
+        .map_err(|_| "Failed to connect to SQLite")?;
+
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .map_err(|_| "Failed to migrate SQLite database")?;
+
+    Ok(pool)
+}
+
+impl MessageRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let database_url =
+            std::env::var("SQLITE_DATABASE_URL").expect("SQLITE_DATABASE_URL is not set");
+
+        Self::connect(&database_url).await
+    }
+
+    pub async fn connect(database_url: &str) -> Result<Self, &'static str> {
+        Ok(Self {
+            pool: connect(database_url).await?,
+        })
+    }
+
+    async fn list_by(
+        &self,
+        column: &str,
+        value: String,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        // newest first so the limit keeps the latest messages
+        let rows: Vec<MessageRow> = sqlx::query_as(&format!(
+            "SELECT * FROM messages WHERE {column} = ? ORDER BY sequence DESC LIMIT ?"
+        ))
+        .bind(value)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| "Failed to get messages from SQLite")?;
+
+        match order_direction {
+            OrderDirection::Ascending => rows.into_iter().rev().map(TryInto::try_into).collect(),
+            OrderDirection::Descending => rows.into_iter().map(TryInto::try_into).collect(),
+        }
+    }
+}
+
+impl MessageRepo for MessageRepoImpl {
+    async fn save(&self, messages: Vec<Message>) -> Result<(), &'static str> {
+        let Some(user_id) = messages.first().map(|message| message.user.id.clone()) else {
+            return Ok(());
+        };
+        if messages.iter().any(|message| message.user.id != user_id) {
+            return Err("Messages must belong to the same user");
+        }
+
+        // write every message in a single transaction so a turn is saved all or nothing
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| "Failed to begin SQLite transaction")?;
+
+        let saved_time = Utc::now();
+        for message in messages {
+            let context = message.context.ok_or("Context is required")?;
+
+            // the sequence is taken in the same statement that writes, so concurrent turns
+            // can't read the same last sequence
+            sqlx::query(
+                "INSERT INTO messages \
+                (id, user_id, sender, text, context_id, context_name, created_time, sequence, \
+                image_url, preview_image_url, image_name, preview_image_name, \
+                image_revised_prompt) \
+                SELECT ?, ?, ?, ?, ?, ?, ?, COALESCE(MAX(sequence), 0) + 1, ?, ?, ?, ?, ? \
+                FROM messages WHERE user_id = ?",
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&user_id)
+            .bind(Sender::from(message.from).as_str())
+            .bind(message.text)
+            .bind(context.id.to_string())
+            .bind(context.name)
+            .bind(timestamp(message.timestamp.unwrap_or(saved_time)))
+            .bind(message.image.as_ref().map(|image| image.url.clone()))
+            .bind(
+                message
+                    .image
+                    .as_ref()
+                    .map(|image| image.preview_url.clone()),
+            )
+            .bind(message.image.as_ref().and_then(|image| image.name.clone()))
+            .bind(
+                message
+                    .image
+                    .as_ref()
+                    .and_then(|image| image.preview_name.clone()),
+            )
+            .bind(message.image.and_then(|image| image.revised_prompt))
+            .bind(&user_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| "Failed to save messages to SQLite")?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| "Failed to commit SQLite transaction")?;
+
+        Ok(())
+    }
+
+    async fn list_by_user_id(
+        &self,
+        user_id: String,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        self.list_by("user_id", user_id, limit, order_direction)
+            .await
+    }
+
+    async fn list_by_context_id(
+        &self,
+        context_id: ContextId,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> Result<Vec<Message>, &'static str> {
+        self.list_by("context_id", context_id.to_string(), limit, order_direction)
+            .await
+    }
+
+    async fn query(&self, query: MessageQuery) -> Result<MessagePage, &'static str> {
+        let cursor = query
+            .page_token
+            .as_deref()
+            .map(MessageKey::from_page_token)
+            .transpose()?;
+        let (direction, after) = match query.order_direction {
+            OrderDirection::Ascending => ("ASC", ">"),
+            OrderDirection::Descending => ("DESC", "<"),
+        };
+
+        let mut builder = message_filters("SELECT * FROM messages", &query);
+        if let Some(cursor) = cursor {
+            builder
+                .push(format!(" AND (created_time, user_id, sequence) {after} ("))
+                .push_bind(timestamp(cursor.created_time))
+                .push(", ")
+                .push_bind(cursor.user_id)
+                .push(", ")
+                .push_bind(cursor.sequence)
+                .push(")");
+        }
+        builder
+            .push(format!(
+                " ORDER BY created_time {direction}, user_id {direction}, sequence {direction} LIMIT "
+            ))
+            .push_bind(query.limit + 1);
+
+        let rows: Vec<MessageRow> = builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| "Failed to query messages from SQLite")?;
+
+        into_page(rows, query.limit, |row| MessageKey {
+            created_time: row.created_time,
+            user_id: row.user_id.clone(),
+            sequence: row.sequence,
+        })
+    }
+
+    async fn count(&self, query: MessageQuery) -> Result<u64, &'static str> {
+        let (count,): (i64,) = message_filters("SELECT COUNT(*) FROM messages", &query)
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|_| "Failed to count messages in SQLite")?;
+
+        Ok(count as u64)
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        let result = sqlx::query("DELETE FROM messages WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| "Failed to delete messages from SQLite")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_created_before(&self, until: DateTime<Utc>) -> Result<u64, &'static str> {
+        let result = sqlx::query("DELETE FROM messages WHERE created_time < ?")
+            .bind(timestamp(until))
+            .execute(&self.pool)
+            .await
+            .map_err(|_| "Failed to delete messages from SQLite")?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// the WHERE clause of a message query, shared by the select and the count
+fn message_filters<'a>(select: &str, query: &MessageQuery) -> QueryBuilder<'a, Sqlite> {
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!("{select} WHERE 1 = 1"));
+    if let Some(user_id) = query.user_id.clone() {
+        builder.push(" AND user_id = ").push_bind(user_id);
+    }
+    if let Some(context_id) = &query.context_id {
+        builder
+            .push(" AND context_id = ")
+            .push_bind(context_id.to_string());
+    }
+    if let Some(from) = query.from {
+        builder
+            .push(" AND sender = ")
+            .push_bind(Sender::from(from).as_str());
+    }
+    if let Some(since) = query.since {
+        builder
+            .push(" AND created_time >= ")
+            .push_bind(timestamp(since));
+    }
+    if let Some(until) = query.until {
+        builder
+            .push(" AND created_time < ")
+            .push_bind(timestamp(until));
+    }
+    match query.has_image {
+        Some(true) => {
+            builder.push(" AND image_url IS NOT NULL");
+        }
+        Some(false) => {
+            builder.push(" AND image_url IS NULL");
+        }
+        None => {}
+    }
+
+    builder
+}
+
+// fixed-width so timestamps compare correctly as text
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[derive(FromRow, Debug)]
+struct MessageRow {
+    user_id: String,
+    sender: String,
+    text: String,
+    context_id: String,
+    context_name: String,
+    created_time: DateTime<Utc>,
+    sequence: i64,
+    image_url: Option<String>,
+    preview_image_url: Option<String>,
+    image_name: Option<String>,
+    preview_image_name: Option<String>,
+    image_revised_prompt: Option<String>,
+}
+
+enum Sender {
+    User,
+    Bot,
+}
+
+impl Sender {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Bot => "bot",
+        }
+    }
+}
+
+impl From<Actor> for Sender {
+    fn from(actor: Actor) -> Self {
+        match actor {
+            Actor::User => Self::User,
+            Actor::Bot => Self::Bot,
+        }
+    }
+}
+
+impl TryFrom<MessageRow> for Message {
+    type Error = &'static str;
+
+    fn try_from(row: MessageRow) -> Result<Self, Self::Error> {
+        let from = match row.sender.as_str() {
+            "user" => Actor::User,
+            "bot" => Actor::Bot,
+            _ => return Err("Invalid message sender"),
+        };
+
+        Ok(Message {
+            user: User { id: row.user_id },
+            from,
+            text: row.text,
+            context: Some(Context {
+                id: row.context_id.try_into()?,
+                name: row.context_name,
+            }),
+            reply_token: None,
+            quick_replies: vec![],
+            image: row
+                .image_url
+                .zip(row.preview_image_url)
+                .map(|(url, preview_url)| ImageMessage {
+                    url,
+                    preview_url,
+                    name: row.image_name,
+                    preview_name: row.preview_image_name,
+                    revised_prompt: row.image_revised_prompt,
+                }),
+            timestamp: Some(row.created_time),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UsageRepoImpl {
+    pool: SqlitePool,
+}
+
+impl UsageRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let database_url =
+            std::env::var("SQLITE_DATABASE_URL").expect("SQLITE_DATABASE_URL is not set");
+
+        Self::connect(&database_url).await
+    }
+
+    pub async fn connect(database_url: &str) -> Result<Self, &'static str> {
+        Ok(Self {
+            pool: connect(database_url).await?,
+        })
+    }
+}
+
+impl UsageRepo for UsageRepoImpl {
+    async fn save(&self, records: Vec<UsageRecord>) -> Result<(), &'static str> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| "Failed to begin SQLite transaction")?;
+
+        for record in records {
+            sqlx::query(
+                "INSERT INTO usage \
+                (id, user_id, context_id, model, prompt_tokens, completion_tokens, images, cost, \
+                created_time) \
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(record.user_id)
+            .bind(record.context_id.map(|context_id| context_id.to_string()))
+            .bind(record.model)
+            .bind(record.prompt_tokens as i64)
+            .bind(record.completion_tokens as i64)
+            .bind(record.images as i64)
+            .bind(record.cost)
+            .bind(timestamp(record.created_time))
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| "Failed to save usage to SQLite")?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| "Failed to commit SQLite transaction")?;
+
+        Ok(())
+    }
+
+    async fn query(&self, query: UsageQuery) -> Result<Vec<UsageRecord>, &'static str> {
+        let mut builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT * FROM usage WHERE 1 = 1");
+        if let Some(user_id) = query.user_id {
+            builder.push(" AND user_id = ").push_bind(user_id);
+        }
+        if let Some(since) = query.since {
+            builder
+                .push(" AND created_time >= ")
+                .push_bind(timestamp(since));
+        }
+        if let Some(until) = query.until {
+            builder
+                .push(" AND created_time < ")
+                .push_bind(timestamp(until));
+        }
+        builder.push(" ORDER BY created_time ASC");
+
+        let rows: Vec<UsageRow> = builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| "Failed to query usage from SQLite")?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        let result = sqlx::query("DELETE FROM usage WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| "Failed to delete usage from SQLite")?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(FromRow, Debug)]
+struct UsageRow {
+    user_id: String,
+    context_id: Option<String>,
+    model: String,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    images: i64,
+    cost: f64,
+    created_time: DateTime<Utc>,
+}
+
+impl TryFrom<UsageRow> for UsageRecord {
+    type Error = &'static str;
+
+    fn try_from(row: UsageRow) -> Result<Self, Self::Error> {
+        Ok(UsageRecord {
+            user_id: row.user_id,
+            context_id: row.context_id.map(TryInto::try_into).transpose()?,
+            model: row.model,
+            prompt_tokens: row.prompt_tokens as u64,
+            completion_tokens: row.completion_tokens as u64,
+            images: row.images as u64,
+            cost: row.cost,
+            created_time: row.created_time,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UserRepoImpl {
+    pool: SqlitePool,
+}
+
+impl UserRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let database_url =
+            std::env::var("SQLITE_DATABASE_URL").expect("SQLITE_DATABASE_URL is not set");
+
+        Self::connect(&database_url).await
+    }
+
+    pub async fn connect(database_url: &str) -> Result<Self, &'static str> {
+        Ok(Self {
+            pool: connect(database_url).await?,
+        })
+    }
+}
+
+impl UserRepo for UserRepoImpl {
+    async fn get_preferences(&self, user_id: String) -> Result<UserPreferences, &'static str> {
+        let row: Option<UserRow> = sqlx::query_as("SELECT * FROM users WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| "Failed to get user from SQLite")?;
+
+        row.map(TryInto::try_into)
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    async fn save_preferences(
+        &self,
+        user_id: String,
+        preferences: UserPreferences,
+    ) -> Result<(), &'static str> {
+        sqlx::query(
+            "INSERT INTO users \
+            (user_id, image_size, image_orientation, image_count, reply_language, persona, \
+            verbosity, history_opt_out) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+            ON CONFLICT (user_id) DO UPDATE SET \
+            image_size = excluded.image_size, \
+            image_orientation = excluded.image_orientation, \
+            image_count = excluded.image_count, \
+            reply_language = excluded.reply_language, \
+            persona = excluded.persona, \
+            verbosity = excluded.verbosity, \
+            history_opt_out = excluded.history_opt_out",
+        )
+        .bind(user_id)
+        .bind(preferences.image_size.map(|size| match size {
+            ImageSizePreference::Small => "small",
+            ImageSizePreference::Medium => "medium",
+            ImageSizePreference::Large => "large",
+        }))
+        .bind(
+            preferences
+                .image_orientation
+                .map(|orientation| match orientation {
+                    ImageOrientation::Square => "square",
+                    ImageOrientation::Landscape => "landscape",
+                    ImageOrientation::Portrait => "portrait",
+                }),
+        )
+        .bind(preferences.image_count)
+        .bind(preferences.reply_language)
+        .bind(preferences.persona)
+        .bind(preferences.verbosity.map(|verbosity| match verbosity {
+            Verbosity::Concise => "concise",
+            Verbosity::Normal => "normal",
+            Verbosity::Detailed => "detailed",
+        }))
+        .bind(preferences.history_opt_out)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| "Failed to save user to SQLite")?;
+
+        Ok(())
+    }
+
+    async fn delete_preferences(&self, user_id: String) -> Result<(), &'static str> {
+        sqlx::query("DELETE FROM users WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| "Failed to delete user from SQLite")?;
+
+        Ok(())
+    }
+}
+
+#[derive(FromRow, Debug)]
+struct UserRow {
+    image_size: Option<String>,
+    image_orientation: Option<String>,
+    image_count: Option<u8>,
+    reply_language: Option<String>,
+    persona: Option<String>,
+    verbosity: Option<String>,
+    history_opt_out: bool,
+}
+
+impl TryFrom<UserRow> for UserPreferences {
+    type Error = &'static str;
+
+    fn try_from(row: UserRow) -> Result<Self, Self::Error> {
+        Ok(UserPreferences {
+            image_size: row
+                .image_size
+                .map(|size| match size.as_str() {
+                    "small" => Ok(ImageSizePreference::Small),
+                    "medium" => Ok(ImageSizePreference::Medium),
+                    "large" => Ok(ImageSizePreference::Large),
+                    _ => Err("Invalid image size preference"),
+                })
+                .transpose()?,
+            image_orientation: row
+                .image_orientation
+                .map(|orientation| match orientation.as_str() {
+                    "square" => Ok(ImageOrientation::Square),
+                    "landscape" => Ok(ImageOrientation::Landscape),
+                    "portrait" => Ok(ImageOrientation::Portrait),
+                    _ => Err("Invalid image orientation preference"),
+                })
+                .transpose()?,
+            image_count: row.image_count,
+            reply_language: row.reply_language,
+            persona: row.persona,
+            verbosity: row
+                .verbosity
+                .map(|verbosity| match verbosity.as_str() {
+                    "concise" => Ok(Verbosity::Concise),
+                    "normal" => Ok(Verbosity::Normal),
+                    "detailed" => Ok(Verbosity::Detailed),
+                    _ => Err("Invalid verbosity preference"),
+                })
+                .transpose()?,
+            history_opt_out: row.history_opt_out,
+        })
+    }
+}
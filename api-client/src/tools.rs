@@ -0,0 +1,446 @@
+use crate::{gpt::schema, message_repo::MessageRepoImpl};
+use chrono::prelude::*;
+use domain::{Actor, MessageQuery, MessageRepo, OrderDirection};
+use serde::Deserialize;
+use serde_json::json;
+use std::{future::Future, pin::Pin, sync::Arc};
+
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<String, &'static str>> + Send + 'a>>;
+
+// who the chat is with, so tools only ever see that user's data
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    pub user_id: String,
+    pub history_opt_out: bool,
+}
+
+// a function the chat model can call, the result is fed back to it as text
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    // JSON schema of the arguments
+    fn parameters(&self) -> serde_json::Value;
+    fn call<'a>(&'a self, arguments: serde_json::Value, context: &'a ToolContext)
+        -> ToolFuture<'a>;
+
+    // hidden from the model when false
+    fn available(&self, _context: &ToolContext) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // current time, calculator and the user's past conversations
+    pub fn builtin(message_repo: MessageRepoImpl) -> Self {
+        Self::new()
+            .register(CurrentTime)
+            .register(Calculator)
+            .register(ConversationSearch { message_repo })
+    }
+
+    pub fn register(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    // None when there is nothing to offer, the request then carries no tools
+    pub fn definitions(&self, context: &ToolContext) -> Option<Vec<schema::Tool>> {
+        let definitions = self
+            .tools
+            .iter()
+            .filter(|tool| tool.available(context))
+            .map(|tool| schema::Tool {
+                r#type: "function".to_string(),
+                function: schema::FunctionDefinition {
+                    name: tool.name().to_string(),
+                    description: tool.description().to_string(),
+                    parameters: tool.parameters(),
+                },
+            })
+            .collect::<Vec<schema::Tool>>();
+
+        (!definitions.is_empty()).then_some(definitions)
+    }
+
+    // failures are reported to the model, which can retry or answer without the tool
+    pub async fn call(&self, call: &schema::FunctionCall, context: &ToolContext) -> String {
+        let Some(tool) = self
+            .tools
+            .iter()
+            .find(|tool| tool.name() == call.name && tool.available(context))
+        else {
+            return format!("Error: unknown tool {}", call.name);
+        };
+        let Ok(arguments) = serde_json::from_str(&call.arguments) else {
+            return "Error: arguments are not valid JSON".to_string();
+        };
+
+        match tool.call(arguments, context).await {
+            Ok(result) => result,
+            Err(error) => format!("Error: {}", error),
+        }
+    }
+}
+
+fn arguments<T: for<'de> Deserialize<'de>>(
+    arguments: serde_json::Value,
+) -> Result<T, &'static str> {
+    serde_json::from_value(arguments).map_err(|_| "Invalid arguments")
+}
+
+struct CurrentTime;
+
+#[derive(Deserialize)]
+struct CurrentTimeArguments {
+    utc_offset_minutes: Option<i32>,
+}
+
+impl Tool for CurrentTime {
+    fn name(&self) -> &'static str {
+        "current_time"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the current date and time. Pass the UTC offset of the user's time zone if it is known."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "utc_offset_minutes": {
+                    "type": "integer",
+                    "description": "e.g. 540 for Japan Standard Time, UTC when omitted"
+                }
+            }
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        arguments: serde_json::Value,
+        _context: &'a ToolContext,
+    ) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let arguments: CurrentTimeArguments = self::arguments(arguments)?;
+            let offset = FixedOffset::east_opt(arguments.utc_offset_minutes.unwrap_or(0) * 60)
+                .ok_or("Invalid UTC offset")?;
+            let now = Utc::now().with_timezone(&offset);
+
+            Ok(format!("{} ({})", now.to_rfc3339(), now.format("%A")))
+        })
+    }
+}
+
+struct Calculator;
+
+#[derive(Deserialize)]
+struct CalculatorArguments {
+    expression: String,
+}
+
+impl Tool for Calculator {
+    fn name(&self) -> &'static str {
+        "calculator"
+    }
+
+    fn description(&self) -> &'static str {
+        "Evaluate an arithmetic expression exactly instead of calculating it yourself."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "numbers with + - * / % ^ and parentheses, e.g. (1.5 + 2) * 3^2"
+                }
+            },
+            "required": ["expression"]
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        arguments: serde_json::Value,
+        _context: &'a ToolContext,
+    ) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let arguments: CalculatorArguments = self::arguments(arguments)?;
+            let value = evaluate(&arguments.expression)?;
+
+            // whole numbers are shown without a trailing .0
+            Ok(if value.fract() == 0.0 && value.abs() < 1e15 {
+                format!("{}", value as i64)
+            } else {
+                format!("{}", value)
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    Operator(char),
+    Open,
+    Close,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            ' ' | '\t' | ',' => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '+' | '-' | '*' | '/' | '%' | '^' => tokens.push(Token::Operator(char)),
+            '×' => tokens.push(Token::Operator('*')),
+            '÷' => tokens.push(Token::Operator('/')),
+            '0'..='9' | '.' => {
+                let mut number = char.to_string();
+                while let Some(next) = chars.next_if(|next| next.is_ascii_digit() || *next == '.') {
+                    number.push(next);
+                }
+                tokens.push(Token::Number(number.parse().map_err(|_| "Invalid number")?));
+            }
+            _ => return Err("Unsupported character in expression"),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn evaluate(expression: &str) -> Result<f64, &'static str> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.expression()?;
+    if parser.position != parser.tokens.len() {
+        return Err("Unexpected token in expression");
+    }
+    if !value.is_finite() {
+        return Err("Result is not a finite number");
+    }
+
+    Ok(value)
+}
+
+// expression = term (("+" | "-") term)*
+// term       = unary (("*" | "/" | "%") unary)*
+// unary      = "-" unary | power
+// power      = primary ("^" unary)?
+// primary    = number | "(" expression ")"
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    // every recursion goes through unary, so this bounds the stack the input can take
+    depth: usize,
+}
+
+const MAX_DEPTH: usize = 64;
+
+impl Parser {
+    fn next_if(&mut self, token: Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(&token);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn expression(&mut self) -> Result<f64, &'static str> {
+        let mut value = self.term()?;
+        loop {
+            if self.next_if(Token::Operator('+')) {
+                value += self.term()?;
+            } else if self.next_if(Token::Operator('-')) {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, &'static str> {
+        let mut value = self.unary()?;
+        loop {
+            if self.next_if(Token::Operator('*')) {
+                value *= self.unary()?;
+            } else if self.next_if(Token::Operator('/')) {
+                value /= self.unary()?;
+            } else if self.next_if(Token::Operator('%')) {
+                value %= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, &'static str> {
+        if self.depth >= MAX_DEPTH {
+            return Err("Expression is nested too deeply");
+        }
+        self.depth += 1;
+        let value = if self.next_if(Token::Operator('-')) {
+            self.unary().map(|value| -value)
+        } else if self.next_if(Token::Operator('+')) {
+            self.unary()
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+
+        value
+    }
+
+    fn power(&mut self) -> Result<f64, &'static str> {
+        let base = self.primary()?;
+        if self.next_if(Token::Operator('^')) {
+            return Ok(base.powf(self.unary()?));
+        }
+
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<f64, &'static str> {
+        if self.next_if(Token::Open) {
+            let value = self.expression()?;
+            if !self.next_if(Token::Close) {
+                return Err("Missing closing parenthesis");
+            }
+            return Ok(value);
+        }
+
+        match self.tokens.get(self.position) {
+            Some(Token::Number(number)) => {
+                self.position += 1;
+                Ok(*number)
+            }
+            _ => Err("Expected a number"),
+        }
+    }
+}
+
+struct ConversationSearch {
+    message_repo: MessageRepoImpl,
+}
+
+#[derive(Deserialize)]
+struct ConversationSearchArguments {
+    keywords: Vec<String>,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+}
+
+// most recent messages read per search, and matches returned to the model
+const SEARCHED_MESSAGES: u32 = 200;
+const FOUND_MESSAGES: usize = 20;
+
+impl Tool for ConversationSearch {
+    fn name(&self) -> &'static str {
+        "search_past_conversations"
+    }
+
+    fn description(&self) -> &'static str {
+        "Search the user's past conversations with you for messages containing any of the keywords."
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "keywords": {
+                    "type": "array",
+                    "items": { "type": "string" }
+                },
+                "since": {
+                    "type": "string",
+                    "description": "first day to search, YYYY-MM-DD"
+                },
+                "until": {
+                    "type": "string",
+                    "description": "last day to search, YYYY-MM-DD"
+                }
+            },
+            "required": ["keywords"]
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        arguments: serde_json::Value,
+        context: &'a ToolContext,
+    ) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let arguments: ConversationSearchArguments = self::arguments(arguments)?;
+            let start_of_day = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+            let page = self
+                .message_repo
+                .query(MessageQuery {
+                    user_id: Some(context.user_id.clone()),
+                    since: arguments.since.map(start_of_day),
+                    until: arguments
+                        .until
+                        .and_then(|until| until.succ_opt())
+                        .map(start_of_day),
+                    limit: SEARCHED_MESSAGES,
+                    order_direction: OrderDirection::Descending,
+                    ..Default::default()
+                })
+                .await?;
+
+            let keywords = arguments
+                .keywords
+                .iter()
+                .map(|keyword| keyword.to_lowercase())
+                .collect::<Vec<String>>();
+            let found = page
+                .messages
+                .into_iter()
+                .filter(|message| {
+                    let text = message.text.to_lowercase();
+                    keywords.iter().any(|keyword| text.contains(keyword))
+                })
+                .take(FOUND_MESSAGES)
+                .map(|message| {
+                    format!(
+                        "[{}] {}: {}",
+                        message
+                            .timestamp
+                            .map(|timestamp| timestamp.to_rfc3339())
+                            .unwrap_or_default(),
+                        match message.from {
+                            Actor::User => "User",
+                            Actor::Bot => "You",
+                        },
+                        message.text
+                    )
+                })
+                .collect::<Vec<String>>();
+
+            if found.is_empty() {
+                return Ok("No matching messages".to_string());
+            }
+            Ok(found.join("\n"))
+        })
+    }
+
+    // users who opted out of history have nothing stored to search
+    fn available(&self, context: &ToolContext) -> bool {
+        !context.history_opt_out
+    }
+}
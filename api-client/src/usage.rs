@@ -0,0 +1,155 @@
+use chrono::Utc;
+use domain::UsageRecord;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+// USD per million tokens and per image at the default size and quality
+const PRICES: [(&str, Price); 5] = [
+    (
+        "gpt-4o",
+        Price {
+            prompt: 2.5,
+            completion: 10.0,
+            image: 0.0,
+        },
+    ),
+    (
+        "gpt-4o-mini",
+        Price {
+            prompt: 0.15,
+            completion: 0.6,
+            image: 0.0,
+        },
+    ),
+    (
+        "dall-e-2",
+        Price {
+            prompt: 0.0,
+            completion: 0.0,
+            image: 0.02,
+        },
+    ),
+    (
+        "dall-e-3",
+        Price {
+            prompt: 0.0,
+            completion: 0.0,
+            image: 0.04,
+        },
+    ),
+    (
+        "gpt-image-1",
+        Price {
+            prompt: 0.0,
+            completion: 0.0,
+            image: 0.042,
+        },
+    ),
+];
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Price {
+    pub prompt: f64,
+    pub completion: f64,
+    pub image: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: HashMap<String, Price>,
+}
+
+impl PriceTable {
+    // PRICE_TABLE_FILE is a JSON object of model names to prices, merged over the built-in ones
+    pub fn new() -> Result<Self, &'static str> {
+        let mut prices = PRICES
+            .iter()
+            .map(|(model, price)| (model.to_string(), *price))
+            .collect::<HashMap<String, Price>>();
+
+        if let Ok(path) = std::env::var("PRICE_TABLE_FILE") {
+            let content =
+                std::fs::read_to_string(path).map_err(|_| "Failed to read PRICE_TABLE_FILE")?;
+            let overrides: HashMap<String, Price> =
+                serde_json::from_str(&content).map_err(|_| "Invalid PRICE_TABLE_FILE")?;
+            prices.extend(overrides);
+        }
+
+        Ok(Self { prices })
+    }
+
+    // responses name dated snapshots such as gpt-4o-2024-08-06, so the longest matching prefix wins
+    pub fn cost(
+        &self,
+        model: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        images: u64,
+    ) -> f64 {
+        let Some(price) = self
+            .prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| price)
+        else {
+            return 0.0;
+        };
+
+        (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion)
+            / 1_000_000.0
+            + images as f64 * price.image
+    }
+}
+
+// collects the usage of a user's calls, shared by the clients metered with it
+#[derive(Debug, Clone)]
+pub struct UsageMeter {
+    user_id: String,
+    records: Arc<Mutex<Vec<UsageRecord>>>,
+}
+
+impl UsageMeter {
+    pub fn new(user_id: String) -> Self {
+        Self {
+            user_id,
+            records: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        model: String,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        images: u64,
+        cost: f64,
+    ) {
+        self.lock().push(UsageRecord {
+            user_id: self.user_id.clone(),
+            context_id: None,
+            model,
+            prompt_tokens,
+            completion_tokens,
+            images,
+            cost,
+            created_time: Utc::now(),
+        });
+    }
+
+    // the records so far, the meter starts over empty
+    pub fn take(&self) -> Vec<UsageRecord> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    // records are only ever pushed whole, so a poisoned lock still holds valid ones
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<UsageRecord>> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{firestore, memory, sqlite};
+use domain::{UsageQuery, UsageRecord, UsageRepo};
+
+// usage repository backend selected by USAGE_REPO, the message repository's by default
+#[derive(Clone, Debug)]
+pub enum UsageRepoImpl {
+    Firestore(firestore::UsageRepoImpl),
+    Sqlite(sqlite::UsageRepoImpl),
+    Memory(memory::UsageRepoImpl),
+}
+
+impl UsageRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let backend = std::env::var("USAGE_REPO")
+            .or(std::env::var("MESSAGE_REPO"))
+            .unwrap_or("firestore".to_string());
+
+        match backend.as_str() {
+            "firestore" => Ok(Self::Firestore(firestore::UsageRepoImpl::new().await?)),
+            "sqlite" => Ok(Self::Sqlite(sqlite::UsageRepoImpl::new().await?)),
+            "memory" => Ok(Self::Memory(memory::UsageRepoImpl::new())),
+            _ => Err("Invalid USAGE_REPO, expected one of firestore, sqlite or memory"),
+        }
+    }
+}
+
+impl UsageRepo for UsageRepoImpl {
+    async fn save(&self, records: Vec<UsageRecord>) -> Result<(), &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.save(records).await,
+            Self::Sqlite(repo) => repo.save(records).await,
+            Self::Memory(repo) => repo.save(records).await,
+        }
+    }
+
+    async fn query(&self, query: UsageQuery) -> Result<Vec<UsageRecord>, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.query(query).await,
+            Self::Sqlite(repo) => repo.query(query).await,
+            Self::Memory(repo) => repo.query(query).await,
+        }
+    }
+
+    async fn delete_by_user_id(&self, user_id: String) -> Result<u64, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.delete_by_user_id(user_id).await,
+            Self::Sqlite(repo) => repo.delete_by_user_id(user_id).await,
+            Self::Memory(repo) => repo.delete_by_user_id(user_id).await,
+        }
+    }
+}
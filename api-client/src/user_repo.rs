@@ -0,0 +1,55 @@
+use crate::{firestore, memory, sqlite};
+use domain::{UserPreferences, UserRepo};
+
+// user repository backend selected by USER_REPO, the message repository's by default
+#[derive(Clone, Debug)]
+pub enum UserRepoImpl {
+    Firestore(firestore::UserRepoImpl),
+    Sqlite(sqlite::UserRepoImpl),
+    Memory(memory::UserRepoImpl),
+}
+
+impl UserRepoImpl {
+    pub async fn new() -> Result<Self, &'static str> {
+        let backend = std::env::var("USER_REPO")
+            .or(std::env::var("MESSAGE_REPO"))
+            .unwrap_or("firestore".to_string());
+
+        match backend.as_str() {
+            "firestore" => Ok(Self::Firestore(firestore::UserRepoImpl::new().await?)),
+            "sqlite" => Ok(Self::Sqlite(sqlite::UserRepoImpl::new().await?)),
+            "memory" => Ok(Self::Memory(memory::UserRepoImpl::new())),
+            _ => Err("Invalid USER_REPO, expected one of firestore, sqlite or memory"),
+        }
+    }
+}
+
+impl UserRepo for UserRepoImpl {
+    async fn get_preferences(&self, user_id: String) -> Result<UserPreferences, &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.get_preferences(user_id).await,
+            Self::Sqlite(repo) => repo.get_preferences(user_id).await,
+            Self::Memory(repo) => repo.get_preferences(user_id).await,
+        }
+    }
+
+    async fn save_preferences(
+        &self,
+        user_id: String,
+        preferences: UserPreferences,
+    ) -> Result<(), &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.save_preferences(user_id, preferences).await,
+            Self::Sqlite(repo) => repo.save_preferences(user_id, preferences).await,
+            Self::Memory(repo) => repo.save_preferences(user_id, preferences).await,
+        }
+    }
+
+    async fn delete_preferences(&self, user_id: String) -> Result<(), &'static str> {
+        match self {
+            Self::Firestore(repo) => repo.delete_preferences(user_id).await,
+            Self::Sqlite(repo) => repo.delete_preferences(user_id).await,
+            Self::Memory(repo) => repo.delete_preferences(user_id).await,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -15,7 +16,7 @@ impl Context {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ContextId(Uuid);
 
 impl ContextId {
@@ -24,16 +25,24 @@ impl ContextId {
     }
 }
 
+impl Default for ContextId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TryFrom<String> for ContextId {
     type Error = &'static str;
 
     fn try_from(value: String) -> Result<Self, &'static str> {
-        Ok(Self(Uuid::parse_str(&value).expect("Invalid UUID")))
+        Uuid::parse_str(&value)
+            .map(Self)
+            .map_err(|_| "Invalid UUID")
     }
 }
 
-impl ToString for ContextId {
-    fn to_string(&self) -> String {
-        self.0.to_string()
+impl fmt::Display for ContextId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
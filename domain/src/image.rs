@@ -1,5 +1,15 @@
+use crate::provenance::{self, ImageProvenance};
 use base64::{engine::general_purpose::STANDARD, Engine};
-use image::{load_from_memory, ImageBuffer, ImageFormat, Rgba};
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType as PngFilterType, PngEncoder},
+    },
+    imageops::{self, FilterType},
+    DynamicImage, ImageFormat as CodecFormat, ImageReader, Rgba, RgbaImage,
+};
+use sha2::{Digest, Sha256};
+use std::fmt;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
@@ -8,94 +18,500 @@ use uuid::Uuid;
 pub struct Image {
     pub id: Uuid,
     pub path: Option<PathBuf>,
-    pub data: ImageData,
+    pub data: Vec<u8>,
+    // detected from the bytes, not from a file name or a header
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
     pub is_preview: bool,
 }
 
 impl Image {
-    pub fn default(data: ImageData) -> Self {
-        Self {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, ImageError> {
+        let format = ImageFormat::detect(&data)?;
+        let (width, height) = ImageReader::with_format(Cursor::new(&data), format.into())
+            .into_dimensions()
+            .map_err(|_| ImageError::Decode)?;
+
+        Ok(Self {
             id: Uuid::new_v4(),
             path: None,
             data,
+            format,
+            width,
+            height,
             is_preview: false,
-        }
+        })
     }
-    pub fn from_base64(base64: String) -> Self {
-        Self::default(ImageData::Base64(base64))
+
+    pub fn from_base64(base64: String) -> Result<Self, ImageError> {
+        let data = STANDARD
+            .decode(base64)
+            .map_err(|_| ImageError::InvalidBase64)?;
+
+        Self::from_bytes(data)
     }
 
     pub fn file_name(&self) -> String {
         format!(
-            "{}{}.png",
+            "{}{}.{}",
             if self.is_preview { "preview_" } else { "" },
-            &self.id
+            &self.id,
+            self.format.extension()
         )
     }
 
-    pub fn save(&self, output_dir: String) -> Result<Self, &'static str> {
-        let bytes = self.to_bytes().expect("Failed to get image bytes");
-        let img = load_from_memory(&bytes).expect("Failed to decode image");
+    // bytes
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
 
-        let file_name = self.file_name();
-        let path = Path::new(&output_dir).join(file_name);
-        img.save_with_format(path.clone(), ImageFormat::Png)
-            .expect("Failed to save image");
+    // SHA-256 of the pixels, identical images share the same hash whatever metadata they carry,
+    // so it doesn't tell apart two files that only differ in their provenance
+    pub fn content_hash(&self) -> Result<String, ImageError> {
+        let img = self.decode()?;
+        let mut hasher = Sha256::new();
+        hasher.update(img.width().to_be_bytes());
+        hasher.update(img.height().to_be_bytes());
+        hasher.update(img.as_bytes());
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    // the bytes are written as they are, so the file keeps its real format
+    pub fn save(&self, output_dir: String) -> Result<Self, ImageError> {
+        let path = Path::new(&output_dir).join(self.file_name());
+        std::fs::write(&path, &self.data).map_err(|_| ImageError::Io)?;
 
         Ok(Self {
-            id: self.id,
             path: Some(path),
-            data: ImageData::Bytes(bytes),
-            is_preview: self.is_preview,
+            ..self.clone()
         })
     }
 
-    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
-        match &self.data {
-            ImageData::Base64(base64) => {
-                Ok(STANDARD.decode(base64).expect("Failed to decode base64"))
-            }
-            ImageData::Bytes(bytes) => Ok(bytes.clone()),
+    // applies the edits in order, the result is a lossless PNG
+    pub fn edit(&self, edits: &[ImageEdit]) -> Result<Self, ImageError> {
+        let mut img = self.decode()?;
+        for edit in edits {
+            img = match edit {
+                ImageEdit::Grayscale => img.grayscale(),
+                ImageEdit::RotateLeft => img.rotate270(),
+                ImageEdit::RotateRight => img.rotate90(),
+                ImageEdit::Rotate180 => img.rotate180(),
+                ImageEdit::FlipHorizontal => img.fliph(),
+                ImageEdit::FlipVertical => img.flipv(),
+                ImageEdit::CropSquare => {
+                    let side = img.width().min(img.height());
+                    img.crop_imm(
+                        (img.width() - side) / 2,
+                        (img.height() - side) / 2,
+                        side,
+                        side,
+                    )
+                }
+                // doubles the size, but never beyond what an original may be
+                ImageEdit::Upscale => {
+                    let dimension = (img.width().max(img.height()) * 2)
+                        .min(ImageBudget::ORIGINAL.max_dimension);
+                    img.resize(dimension, dimension, FilterType::Lanczos3)
+                }
+            };
         }
+
+        let data = encode(&img, EncodeFormat::Png, 100)?;
+
+        Ok(Self {
+            is_preview: self.is_preview,
+            ..Self::from_bytes(data)?
+        })
     }
 
-    pub fn to_preview(&self) -> Self {
-        let preview = self.resize(512, 512).expect("Failed to resize image");
-        Self {
-            id: preview.id,
-            path: preview.path,
-            data: preview.data,
-            is_preview: true,
+    // tiles the images into a grid, each labeled with its 1-based position
+    pub fn collage(images: &[Self]) -> Result<Self, ImageError> {
+        if images.is_empty() {
+            return Err(ImageError::EmptyCollage);
+        }
+
+        let columns = (images.len() as f64).sqrt().ceil() as u32;
+        let rows = (images.len() as u32).div_ceil(columns);
+        let mut canvas = RgbaImage::from_pixel(
+            columns * COLLAGE_CELL,
+            rows * COLLAGE_CELL,
+            Rgba([255, 255, 255, 255]),
+        );
+
+        for (index, image) in images.iter().enumerate() {
+            let cell_x = index as u32 % columns * COLLAGE_CELL;
+            let cell_y = index as u32 / columns * COLLAGE_CELL;
+
+            // centered in its cell, keeping the aspect ratio
+            let thumbnail = image
+                .decode()?
+                .resize(COLLAGE_CELL, COLLAGE_CELL, FilterType::Lanczos3)
+                .to_rgba8();
+            imageops::overlay(
+                &mut canvas,
+                &thumbnail,
+                (cell_x + (COLLAGE_CELL - thumbnail.width()) / 2).into(),
+                (cell_y + (COLLAGE_CELL - thumbnail.height()) / 2).into(),
+            );
+            draw_label(&mut canvas, index + 1, cell_x, cell_y);
         }
+
+        let data = encode(&DynamicImage::ImageRgba8(canvas), EncodeFormat::Png, 100)?;
+
+        Self::from_bytes(data)
     }
 
-    fn resize(&self, width: u32, height: u32) -> Result<Self, &'static str> {
-        let bytes = self.to_bytes()?;
-        let img = image::load_from_memory(&bytes).expect("Failed to load image for resizing");
+    // to_original and to_preview carry it over to what they encode
+    pub fn with_provenance(&self, provenance: &ImageProvenance) -> Result<Self, ImageError> {
+        let data = match self.format {
+            ImageFormat::Png => provenance::write_png(&self.data, provenance),
+            ImageFormat::Jpeg => provenance::write_jpeg(&self.data, provenance),
+            ImageFormat::WebP | ImageFormat::Gif => return Err(ImageError::UnsupportedFormat),
+        }
+        .ok_or(ImageError::Metadata)?;
 
-        let resized = img.resize(width, height, image::imageops::FilterType::Lanczos3);
+        Ok(Self {
+            data,
+            ..self.clone()
+        })
+    }
 
-        // Convert the resized image to RGBA
-        let rgba_image: ImageBuffer<Rgba<u8>, Vec<u8>> = resized.to_rgba8();
+    pub fn provenance(&self) -> Option<ImageProvenance> {
+        match self.format {
+            ImageFormat::Png => provenance::read_png(&self.data),
+            ImageFormat::Jpeg => provenance::read_jpeg(&self.data),
+            ImageFormat::WebP | ImageFormat::Gif => None,
+        }
+    }
 
-        // Encode the image to PNG
-        let mut png_data = Vec::new();
-        let mut cursor = Cursor::new(&mut png_data);
-        rgba_image
-            .write_to(&mut cursor, ImageFormat::Png)
-            .expect("Failed to encode resized image to PNG");
+    pub fn to_preview(&self) -> Result<Self, ImageError> {
+        let preview = self.encode_within(&ImageBudget::PREVIEW)?;
 
         Ok(Self {
-            id: self.id,
-            path: None,
-            data: ImageData::Bytes(png_data),
-            is_preview: false,
+            is_preview: true,
+            ..preview
         })
     }
+
+    pub fn to_original(&self) -> Result<Self, ImageError> {
+        self.encode_within(&ImageBudget::ORIGINAL)
+    }
+
+    // lowers the JPEG quality first, then shrinks the image until it fits the budget
+    pub fn encode_within(&self, budget: &ImageBudget) -> Result<Self, ImageError> {
+        let img = self.decode()?;
+        // re-encoding drops the metadata, so it is written again before the size is checked
+        let provenance = self.provenance();
+
+        // images are only ever shrunk, never upscaled
+        let mut dimension = img.width().max(img.height()).min(budget.max_dimension);
+        // images already smaller than the minimum are still tried at their own size
+        let min_dimension = MIN_DIMENSION.min(dimension);
+        while dimension >= min_dimension {
+            let resized = if img.width().max(img.height()) > dimension {
+                img.resize(dimension, dimension, FilterType::Lanczos3)
+            } else {
+                img.clone()
+            };
+
+            for quality in budget.format.qualities() {
+                let encoded = Self {
+                    id: self.id,
+                    path: None,
+                    data: encode(&resized, budget.format, *quality)?,
+                    format: budget.format.into(),
+                    width: resized.width(),
+                    height: resized.height(),
+                    is_preview: self.is_preview,
+                };
+                let encoded = match &provenance {
+                    Some(provenance) => encoded.with_provenance(provenance)?,
+                    None => encoded,
+                };
+                if encoded.size() <= budget.max_bytes {
+                    return Ok(encoded);
+                }
+            }
+
+            dimension = dimension * 3 / 4;
+        }
+
+        Err(ImageError::ExceedsBudget)
+    }
+
+    fn decode(&self) -> Result<DynamicImage, ImageError> {
+        image::load_from_memory_with_format(&self.data, self.format.into())
+            .map_err(|_| ImageError::Decode)
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum ImageData {
-    Base64(String),
-    Bytes(Vec<u8>),
+fn encode(img: &DynamicImage, format: EncodeFormat, quality: u8) -> Result<Vec<u8>, ImageError> {
+    let mut data = Vec::new();
+    match format {
+        // JPEG has no alpha channel
+        EncodeFormat::Jpeg => JpegEncoder::new_with_quality(&mut data, quality)
+            .encode_image(&img.to_rgb8())
+            .map_err(|_| ImageError::Encode)?,
+        EncodeFormat::Png => img
+            .to_rgba8()
+            .write_with_encoder(PngEncoder::new_with_quality(
+                &mut data,
+                CompressionType::Best,
+                PngFilterType::Adaptive,
+            ))
+            .map_err(|_| ImageError::Encode)?,
+    }
+
+    Ok(data)
+}
+
+// operations that need no image generation API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageEdit {
+    Grayscale,
+    RotateLeft,
+    RotateRight,
+    Rotate180,
+    FlipHorizontal,
+    FlipVertical,
+    CropSquare,
+    Upscale,
+}
+
+impl ImageEdit {
+    pub const ALL: [Self; 8] = [
+        Self::Grayscale,
+        Self::RotateLeft,
+        Self::RotateRight,
+        Self::Rotate180,
+        Self::FlipHorizontal,
+        Self::FlipVertical,
+        Self::CropSquare,
+        Self::Upscale,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Grayscale => "grayscale",
+            Self::RotateLeft => "rotate_left",
+            Self::RotateRight => "rotate_right",
+            Self::Rotate180 => "rotate_180",
+            Self::FlipHorizontal => "flip_horizontal",
+            Self::FlipVertical => "flip_vertical",
+            Self::CropSquare => "crop_square",
+            Self::Upscale => "upscale",
+        }
+    }
+}
+
+impl TryFrom<&str> for ImageEdit {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|edit| edit.as_str() == value)
+            .ok_or("Invalid image edit")
+    }
+}
+
+// white digits on a black badge in the top left corner of a collage cell
+fn draw_label(canvas: &mut RgbaImage, number: usize, cell_x: u32, cell_y: u32) {
+    let digits = number
+        .to_string()
+        .bytes()
+        .map(|digit| DIGIT_GLYPHS[(digit - b'0') as usize])
+        .collect::<Vec<u16>>();
+
+    // glyphs are 3x5 pixels with a 1 pixel gap, scaled up
+    let scale = LABEL_SCALE;
+    let width = (digits.len() as u32 * 4 + 1) * scale;
+    let height = 7 * scale;
+    let (left, top) = (cell_x + LABEL_MARGIN, cell_y + LABEL_MARGIN);
+    for y in top..top + height {
+        for x in left..left + width {
+            canvas.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+        }
+    }
+
+    for (position, glyph) in digits.iter().enumerate() {
+        let glyph_left = left + (position as u32 * 4 + 1) * scale;
+        for row in 0..5 {
+            for column in 0..3 {
+                if glyph >> (14 - (row * 3 + column)) & 1 == 0 {
+                    continue;
+                }
+                for y in 0..scale {
+                    for x in 0..scale {
+                        canvas.put_pixel(
+                            glyph_left + column * scale + x,
+                            top + (row + 1) * scale + y,
+                            Rgba([255, 255, 255, 255]),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 3x5 bitmaps of 0-9, row by row from the top left
+const DIGIT_GLYPHS: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_001_001_001,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+const COLLAGE_CELL: u32 = 512;
+const LABEL_MARGIN: u32 = 16;
+const LABEL_SCALE: u32 = 8;
+
+// smallest width or height tried before giving up on a budget
+const MIN_DIMENSION: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Gif,
+}
+
+impl ImageFormat {
+    pub fn detect(data: &[u8]) -> Result<Self, ImageError> {
+        match image::guess_format(data) {
+            Ok(CodecFormat::Png) => Ok(Self::Png),
+            Ok(CodecFormat::Jpeg) => Ok(Self::Jpeg),
+            Ok(CodecFormat::WebP) => Ok(Self::WebP),
+            Ok(CodecFormat::Gif) => Ok(Self::Gif),
+            _ => Err(ImageError::UnsupportedFormat),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+}
+
+impl From<ImageFormat> for CodecFormat {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Png => Self::Png,
+            ImageFormat::Jpeg => Self::Jpeg,
+            ImageFormat::WebP => Self::WebP,
+            ImageFormat::Gif => Self::Gif,
+        }
+    }
+}
+
+// formats images can be encoded to, LINE only displays JPEG and PNG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeFormat {
+    Jpeg,
+    Png,
+}
+
+impl EncodeFormat {
+    fn qualities(&self) -> &'static [u8] {
+        match self {
+            Self::Jpeg => &[90, 80, 70, 60, 50],
+            // PNG is lossless, only the dimensions can shrink
+            Self::Png => &[100],
+        }
+    }
+}
+
+impl From<EncodeFormat> for ImageFormat {
+    fn from(format: EncodeFormat) -> Self {
+        match format {
+            EncodeFormat::Jpeg => Self::Jpeg,
+            EncodeFormat::Png => Self::Png,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageBudget {
+    pub format: EncodeFormat,
+    pub max_bytes: usize,
+    pub max_dimension: u32,
+}
+
+impl ImageBudget {
+    // LINE accepts previews up to 1 MB
+    pub const PREVIEW: Self = Self {
+        format: EncodeFormat::Jpeg,
+        max_bytes: 1_000_000,
+        max_dimension: 512,
+    };
+    // LINE accepts originals up to 10 MB
+    pub const ORIGINAL: Self = Self {
+        format: EncodeFormat::Png,
+        max_bytes: 10_000_000,
+        max_dimension: 4096,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    InvalidBase64,
+    UnsupportedFormat,
+    Decode,
+    Encode,
+    ExceedsBudget,
+    EmptyCollage,
+    Metadata,
+    Io,
+}
+
+impl ImageError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidBase64 => "Failed to decode base64 image",
+            Self::UnsupportedFormat => "Unsupported image format, expected PNG, JPEG, WebP or GIF",
+            Self::Decode => "Failed to decode image",
+            Self::Encode => "Failed to encode image",
+            Self::ExceedsBudget => "Failed to fit image within the size budget",
+            Self::EmptyCollage => "Failed to build a collage without images",
+            Self::Metadata => "Failed to write image metadata",
+            Self::Io => "Failed to write image file",
+        }
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+// lets callers that report &'static str errors use `?` on image operations
+impl From<ImageError> for &'static str {
+    fn from(error: ImageError) -> Self {
+        error.as_str()
+    }
 }
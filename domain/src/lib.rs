@@ -3,11 +3,23 @@ mod context_repo;
 mod image;
 mod message;
 mod message_repo;
+mod moderation;
+mod object_storage;
+mod provenance;
+mod usage;
+mod usage_repo;
 mod user;
+mod user_repo;
 
 pub use context::*;
 pub use context_repo::*;
 pub use image::*;
 pub use message::*;
 pub use message_repo::*;
+pub use moderation::*;
+pub use object_storage::*;
+pub use provenance::ImageProvenance;
+pub use usage::*;
+pub use usage_repo::*;
 pub use user::*;
+pub use user_repo::*;
@@ -1,5 +1,6 @@
 use crate::context::Context;
 use crate::user::User;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone)]
 pub struct Message {
@@ -9,9 +10,13 @@ pub struct Message {
     pub context: Option<Context>,
     pub reply_token: Option<String>,
     pub image: Option<ImageMessage>,
+    // only shown with bot replies, never persisted
+    pub quick_replies: Vec<QuickReply>,
+    // when the message was sent, stamped at save time if unknown
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Actor {
     User,
     Bot,
@@ -21,4 +26,16 @@ pub enum Actor {
 pub struct ImageMessage {
     pub url: String,
     pub preview_url: String,
+    // storage object of the original, URLs expire but the object can be read back
+    pub name: Option<String>,
+    pub preview_name: Option<String>,
+    // what the image model actually drew when it rewrote the prompt
+    pub revised_prompt: Option<String>,
+}
+
+// button under a bot message, tapping it sends `data` back as the user's message text
+#[derive(Debug, Clone)]
+pub struct QuickReply {
+    pub label: String,
+    pub data: String,
 }
@@ -1,4 +1,6 @@
-use crate::message::Message;
+use crate::context::ContextId;
+use crate::message::{Actor, Message};
+use chrono::{DateTime, Utc};
 use mockall::automock;
 use std::future::Future;
 
@@ -11,13 +13,72 @@ pub trait MessageRepo {
         limit: u32,
         order_direction: OrderDirection,
     ) -> impl Future<Output = Result<Vec<Message>, &'static str>>;
+    fn list_by_context_id(
+        &self,
+        context_id: ContextId,
+        limit: u32,
+        order_direction: OrderDirection,
+    ) -> impl Future<Output = Result<Vec<Message>, &'static str>>;
+    fn query(&self, query: MessageQuery)
+        -> impl Future<Output = Result<MessagePage, &'static str>>;
+    // number of messages matching the filters, limit, order and page token are ignored
+    fn count(&self, query: MessageQuery) -> impl Future<Output = Result<u64, &'static str>>;
+    // returns the number of deleted messages
+    fn delete_by_user_id(&self, user_id: String)
+        -> impl Future<Output = Result<u64, &'static str>>;
+    fn delete_created_before(
+        &self,
+        until: DateTime<Utc>,
+    ) -> impl Future<Output = Result<u64, &'static str>>;
 }
 
+#[derive(Debug, Clone, Copy, Default)]
 pub enum OrderDirection {
     Ascending,
+    #[default]
     Descending,
 }
 
+// every filter is optional and they are combined with AND
+#[derive(Debug, Clone)]
+pub struct MessageQuery {
+    pub user_id: Option<String>,
+    pub context_id: Option<ContextId>,
+    pub from: Option<Actor>,
+    // inclusive lower bound of the message timestamp
+    pub since: Option<DateTime<Utc>>,
+    // exclusive upper bound of the message timestamp
+    pub until: Option<DateTime<Utc>>,
+    pub has_image: Option<bool>,
+    pub limit: u32,
+    pub order_direction: OrderDirection,
+    // next_page_token of the previous page, None for the first page
+    pub page_token: Option<String>,
+}
+
+impl Default for MessageQuery {
+    fn default() -> Self {
+        Self {
+            user_id: None,
+            context_id: None,
+            from: None,
+            since: None,
+            until: None,
+            has_image: None,
+            limit: 100,
+            order_direction: OrderDirection::default(),
+            page_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    // None when there are no more messages
+    pub next_page_token: Option<String>,
+}
+
 pub trait ProvideMessageRepo {
     type Repo: MessageRepo;
 
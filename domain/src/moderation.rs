@@ -0,0 +1,17 @@
+use mockall::automock;
+use std::future::Future;
+
+#[derive(Debug, Clone, Default)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    // why the text was flagged, e.g. "violence" or the matched keyword
+    pub categories: Vec<String>,
+}
+
+#[automock]
+pub trait Moderation {
+    fn moderate(
+        &self,
+        text: String,
+    ) -> impl Future<Output = Result<ModerationResult, &'static str>>;
+}
@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use mockall::automock;
+use std::future::Future;
+
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    // path-like key such as "users/{user_id}/{file_name}"
+    pub name: String,
+    pub size: u64,
+    pub created_time: Option<DateTime<Utc>>,
+}
+
+#[automock]
+pub trait ObjectStorage {
+    fn put(
+        &self,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+    ) -> impl Future<Output = Result<StoredObject, &'static str>>;
+    fn get(&self, name: String) -> impl Future<Output = Result<Vec<u8>, &'static str>>;
+    // None if there is no object with the name
+    fn stat(
+        &self,
+        name: String,
+    ) -> impl Future<Output = Result<Option<StoredObject>, &'static str>>;
+    fn delete(&self, name: String) -> impl Future<Output = Result<(), &'static str>>;
+    fn list(
+        &self,
+        prefix: Option<String>,
+    ) -> impl Future<Output = Result<Vec<StoredObject>, &'static str>>;
+    // URL that the messaging app can download the object from
+    fn url(&self, name: String) -> impl Future<Output = Result<String, &'static str>>;
+}
+
+pub trait ProvideObjectStorage {
+    type Storage: ObjectStorage;
+
+    fn provide(&self) -> &Self::Storage;
+}
@@ -0,0 +1,316 @@
+use crate::ContextId;
+use chrono::{DateTime, Utc};
+
+// how a generated image was made, embedded in the image file itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageProvenance {
+    pub prompt: String,
+    pub model: String,
+    pub size: String,
+    pub created_time: DateTime<Utc>,
+    pub context_id: Option<ContextId>,
+}
+
+// keywords follow the predefined PNG text keywords where one fits
+const PROMPT: &str = "Description";
+const MODEL: &str = "Software";
+const SIZE: &str = "Size";
+const CREATED_TIME: &str = "Creation Time";
+const CONTEXT_ID: &str = "Context";
+
+impl ImageProvenance {
+    fn entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![
+            (PROMPT, self.prompt.clone()),
+            (MODEL, self.model.clone()),
+            (SIZE, self.size.clone()),
+            (CREATED_TIME, self.created_time.to_rfc3339()),
+        ];
+        if let Some(context_id) = &self.context_id {
+            entries.push((CONTEXT_ID, context_id.to_string()));
+        }
+
+        entries
+    }
+
+    fn from_entries(entries: Vec<(String, String)>) -> Option<Self> {
+        let value = |keyword: &str| {
+            entries
+                .iter()
+                .find(|(key, _)| key == keyword)
+                .map(|(_, value)| value.clone())
+        };
+
+        Some(Self {
+            prompt: value(PROMPT)?,
+            model: value(MODEL)?,
+            size: value(SIZE)?,
+            created_time: DateTime::parse_from_rfc3339(&value(CREATED_TIME)?)
+                .ok()?
+                .with_timezone(&Utc),
+            context_id: value(CONTEXT_ID).and_then(|id| id.try_into().ok()),
+        })
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// adds an uncompressed iTXt chunk per entry right after IHDR, the pixels are untouched
+pub(crate) fn write_png(data: &[u8], provenance: &ImageProvenance) -> Option<Vec<u8>> {
+    let (_, ihdr) = png_chunks(data)?.into_iter().next()?;
+    let ihdr_end = ihdr.end + 4;
+
+    let mut output = data[..ihdr_end].to_vec();
+    for (keyword, text) in provenance.entries() {
+        // keyword, compression flag and method, empty language tag and translated keyword
+        let mut chunk = keyword.as_bytes().to_vec();
+        chunk.extend_from_slice(&[0, 0, 0, 0, 0]);
+        chunk.extend_from_slice(text.as_bytes());
+
+        output.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        let start = output.len();
+        output.extend_from_slice(b"iTXt");
+        output.extend_from_slice(&chunk);
+        let crc = crc32fast::hash(&output[start..]);
+        output.extend_from_slice(&crc.to_be_bytes());
+    }
+    output.extend_from_slice(&data[ihdr_end..]);
+
+    Some(output)
+}
+
+pub(crate) fn read_png(data: &[u8]) -> Option<ImageProvenance> {
+    let entries = png_chunks(data)?
+        .into_iter()
+        .filter(|(chunk_type, _)| chunk_type == b"iTXt")
+        .filter_map(|(_, range)| {
+            let chunk = &data[range];
+            let keyword_end = chunk.iter().position(|byte| *byte == 0)?;
+            // compressed text is never written by us
+            if chunk.get(keyword_end + 1) != Some(&0) {
+                return None;
+            }
+            let mut rest = chunk.get(keyword_end + 3..)?;
+            // skip the language tag and the translated keyword
+            for _ in 0..2 {
+                let end = rest.iter().position(|byte| *byte == 0)?;
+                rest = &rest[end + 1..];
+            }
+
+            Some((
+                String::from_utf8(chunk[..keyword_end].to_vec()).ok()?,
+                String::from_utf8(rest.to_vec()).ok()?,
+            ))
+        })
+        .collect();
+
+    ImageProvenance::from_entries(entries)
+}
+
+// chunk types with the range of their data
+fn png_chunks(data: &[u8]) -> Option<Vec<([u8; 4], std::ops::Range<usize>)>> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut chunks = vec![];
+    let mut offset = PNG_SIGNATURE.len();
+    while offset + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[offset..offset + 4].try_into().ok()?) as usize;
+        let chunk_type: [u8; 4] = data[offset + 4..offset + 8].try_into().ok()?;
+        let start = offset + 8;
+        if start + length + 4 > data.len() {
+            return None;
+        }
+        chunks.push((chunk_type, start..start + length));
+        if &chunk_type == b"IEND" {
+            break;
+        }
+        offset = start + length + 4;
+    }
+
+    Some(chunks)
+}
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const IMAGE_DESCRIPTION_TAG: u16 = 0x010e;
+const EXIF_IFD_POINTER_TAG: u16 = 0x8769;
+const USER_COMMENT_TAG: u16 = 0x9286;
+const UNICODE: &[u8] = b"UNICODE\0";
+// the length of a JPEG segment includes its own two bytes
+const MAX_SEGMENT_LENGTH: usize = u16::MAX as usize;
+// TIFF header, then IFD0 and the EXIF IFD with a single entry each
+const USER_COMMENT_OFFSET: usize = 8 + 18 + 18;
+
+// JPEG has no text chunks, so the entries go into the EXIF UserComment one per line,
+// as UTF-16 since ImageDescription only holds ASCII
+pub(crate) fn write_jpeg(data: &[u8], provenance: &ImageProvenance) -> Option<Vec<u8>> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+
+    let max_units =
+        (MAX_SEGMENT_LENGTH - 2 - EXIF_HEADER.len() - USER_COMMENT_OFFSET - UNICODE.len()) / 2;
+    let mut comment = UNICODE.to_vec();
+    for unit in user_comment(provenance, max_units) {
+        comment.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    // big-endian TIFF header, IFD0 only points to the EXIF IFD holding the comment
+    let mut tiff = b"MM\0\x2a".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&EXIF_IFD_POINTER_TAG.to_be_bytes());
+    tiff.extend_from_slice(&4u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&26u32.to_be_bytes());
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&USER_COMMENT_TAG.to_be_bytes());
+    tiff.extend_from_slice(&7u16.to_be_bytes());
+    tiff.extend_from_slice(&(comment.len() as u32).to_be_bytes());
+    tiff.extend_from_slice(&(USER_COMMENT_OFFSET as u32).to_be_bytes());
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(&comment);
+
+    // JFIF requires its APP0 segment right after the start of image
+    let mut insert_at = 2;
+    if data.get(2..4) == Some(&[0xff, 0xe0]) {
+        insert_at += 2 + u16::from_be_bytes([*data.get(4)?, *data.get(5)?]) as usize;
+    }
+
+    let length = (2 + EXIF_HEADER.len() + tiff.len()) as u16;
+    let mut output = data.get(..insert_at)?.to_vec();
+    output.extend_from_slice(&[0xff, 0xe1]);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(EXIF_HEADER);
+    output.extend_from_slice(&tiff);
+    output.extend_from_slice(&data[insert_at..]);
+
+    Some(output)
+}
+
+// the prompt goes last, so only it is cut short when the entries don't fit in a segment
+fn user_comment(provenance: &ImageProvenance, max_units: usize) -> Vec<u16> {
+    let (prompt, entries): (Vec<_>, Vec<_>) = provenance
+        .entries()
+        .into_iter()
+        .partition(|(keyword, _)| *keyword == PROMPT);
+    let text = entries
+        .into_iter()
+        .chain(prompt)
+        .map(|(keyword, text)| format!("{}: {}", keyword, text.replace(['\r', '\n'], " ")))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let mut units = vec![];
+    for character in text.chars() {
+        let mut buffer = [0; 2];
+        let encoded = character.encode_utf16(&mut buffer);
+        if units.len() + encoded.len() > max_units {
+            break;
+        }
+        units.extend_from_slice(encoded);
+    }
+
+    units
+}
+
+pub(crate) fn read_jpeg(data: &[u8]) -> Option<ImageProvenance> {
+    let description = jpeg_exif(data).and_then(exif_text)?;
+    let entries = description
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .map(|(keyword, text)| (keyword.to_string(), text.to_string()))
+        .collect();
+
+    ImageProvenance::from_entries(entries)
+}
+
+// TIFF data of the first EXIF segment
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+
+    let mut offset = 2;
+    while offset + 4 <= data.len() && data[offset] == 0xff {
+        let marker = data[offset + 1];
+        // the compressed image data starts, no metadata after this
+        if marker == 0xda {
+            return None;
+        }
+        let length = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let segment = data.get(offset + 4..offset + 2 + length)?;
+        if marker == 0xe1 && segment.starts_with(EXIF_HEADER) {
+            return Some(&segment[EXIF_HEADER.len()..]);
+        }
+        offset += 2 + length;
+    }
+
+    None
+}
+
+// the UserComment, or the ImageDescription that older images were written with
+fn exif_text(tiff: &[u8]) -> Option<String> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes: [u8; 4] = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    // byte range of the value of the tag in the IFD at the offset
+    let value = |ifd: usize, tag: u16| {
+        let entry = (0..u16_at(ifd)? as usize)
+            .map(|index| ifd + 2 + index * 12)
+            .find(|entry| u16_at(*entry) == Some(tag))?;
+        let count = u32_at(entry + 4)? as usize;
+        // values of up to 4 bytes are stored inline
+        let value = if count <= 4 {
+            entry + 8
+        } else {
+            u32_at(entry + 8)? as usize
+        };
+
+        Some(value..value + count)
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let comment = value(ifd, EXIF_IFD_POINTER_TAG)
+        .and_then(|pointer| u32_at(pointer.start))
+        .and_then(|exif_ifd| value(exif_ifd as usize, USER_COMMENT_TAG))
+        .and_then(|comment| tiff.get(comment));
+    if let Some(comment) = comment {
+        let text = comment.strip_prefix(UNICODE)?;
+        let units = text
+            .chunks_exact(2)
+            .map(|bytes| {
+                if big_endian {
+                    u16::from_be_bytes([bytes[0], bytes[1]])
+                } else {
+                    u16::from_le_bytes([bytes[0], bytes[1]])
+                }
+            })
+            .collect::<Vec<u16>>();
+
+        return String::from_utf16(&units).ok();
+    }
+
+    let text = tiff.get(value(ifd, IMAGE_DESCRIPTION_TAG)?)?;
+    String::from_utf8(text.split(|byte| *byte == 0).next()?.to_vec()).ok()
+}
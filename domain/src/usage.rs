@@ -0,0 +1,70 @@
+use crate::context::ContextId;
+use chrono::{DateTime, NaiveDate, Utc};
+
+// one call to a model, tokens and images are zero where the API doesn't charge by them
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    pub user_id: String,
+    pub context_id: Option<ContextId>,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub images: u64,
+    // estimated in USD from the price table at the time of the call
+    pub cost: f64,
+    pub created_time: DateTime<Utc>,
+}
+
+// totals of a user's calls on one UTC day
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageSummary {
+    pub user_id: String,
+    pub day: NaiveDate,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub images: u64,
+    pub cost: f64,
+}
+
+impl UsageSummary {
+    // ordered by day, then user
+    pub fn by_day(records: &[UsageRecord]) -> Vec<Self> {
+        let mut summaries: Vec<Self> = vec![];
+        let mut records = records.iter().collect::<Vec<&UsageRecord>>();
+        records.sort_by(|a, b| {
+            (a.created_time.date_naive(), &a.user_id)
+                .cmp(&(b.created_time.date_naive(), &b.user_id))
+        });
+
+        for record in records {
+            let day = record.created_time.date_naive();
+            let summary = match summaries.last_mut() {
+                Some(summary) if summary.day == day && summary.user_id == record.user_id => summary,
+                _ => {
+                    summaries.push(Self {
+                        user_id: record.user_id.clone(),
+                        day,
+                        requests: 0,
+                        prompt_tokens: 0,
+                        completion_tokens: 0,
+                        images: 0,
+                        cost: 0.0,
+                    });
+                    summaries.last_mut().expect("Summary was just pushed")
+                }
+            };
+            summary.requests += 1;
+            summary.prompt_tokens += record.prompt_tokens;
+            summary.completion_tokens += record.completion_tokens;
+            summary.images += record.images;
+            summary.cost += record.cost;
+        }
+
+        summaries
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
@@ -0,0 +1,33 @@
+use crate::usage::UsageRecord;
+use chrono::{DateTime, Utc};
+use mockall::automock;
+use std::future::Future;
+
+#[automock]
+pub trait UsageRepo {
+    fn save(&self, records: Vec<UsageRecord>) -> impl Future<Output = Result<(), &'static str>>;
+    // oldest first
+    fn query(
+        &self,
+        query: UsageQuery,
+    ) -> impl Future<Output = Result<Vec<UsageRecord>, &'static str>>;
+    // returns the number of deleted records
+    fn delete_by_user_id(&self, user_id: String)
+        -> impl Future<Output = Result<u64, &'static str>>;
+}
+
+// every filter is optional and they are combined with AND
+#[derive(Debug, Clone, Default)]
+pub struct UsageQuery {
+    pub user_id: Option<String>,
+    // inclusive lower bound of the call time
+    pub since: Option<DateTime<Utc>>,
+    // exclusive upper bound of the call time
+    pub until: Option<DateTime<Utc>>,
+}
+
+pub trait ProvideUsageRepo {
+    type Repo: UsageRepo;
+
+    fn provide(&self) -> &Self::Repo;
+}
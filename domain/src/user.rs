@@ -1,12 +1,91 @@
+use crate::image::ImageEdit;
+
 #[derive(Debug, Clone)]
 pub struct User {
     pub id: String,
 }
 
+// None means the global default is used
+#[derive(Debug, Clone, Default)]
+pub struct UserPreferences {
+    pub image_size: Option<ImageSizePreference>,
+    pub image_orientation: Option<ImageOrientation>,
+    pub image_count: Option<u8>,
+    pub reply_language: Option<String>,
+    pub persona: Option<String>,
+    pub verbosity: Option<Verbosity>,
+    // the bot neither keeps nor reads the user's history
+    pub history_opt_out: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSizePreference {
+    Small,
+    Medium,
+    Large,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageOrientation {
+    Square,
+    Landscape,
+    Portrait,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageStyle {
+    Vivid,
+    Natural,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageQuality {
+    Standard,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Concise,
+    Normal,
+    Detailed,
+}
+
 #[derive(Debug, Clone)]
 pub enum UserDemand {
     Chat,
     CreateImage,
+    // edits of the latest image, in the order they are applied
+    EditImage(Vec<ImageEdit>),
+    // redraw the latest image following the message
+    RefineImage,
+    CreateVariation,
+    // describe or answer questions about the latest image
+    DescribeImage,
+    Translate,
+    Summarize,
+    Reminder,
+    Help,
+    Settings,
+}
+
+impl UserDemand {
+    // the same names as converted from, so handlers and config can refer to demands
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Chat => "Chat",
+            Self::CreateImage => "CreateImage",
+            Self::EditImage(_) => "EditImage",
+            Self::RefineImage => "RefineImage",
+            Self::CreateVariation => "CreateVariation",
+            Self::DescribeImage => "DescribeImage",
+            Self::Translate => "Translate",
+            Self::Summarize => "Summarize",
+            Self::Reminder => "Reminder",
+            Self::Help => "Help",
+            Self::Settings => "Settings",
+        }
+    }
 }
 
 impl TryFrom<String> for UserDemand {
@@ -16,6 +95,14 @@ impl TryFrom<String> for UserDemand {
         match value.as_str() {
             "Chat" => Ok(Self::Chat),
             "CreateImage" => Ok(Self::CreateImage),
+            "RefineImage" => Ok(Self::RefineImage),
+            "CreateVariation" => Ok(Self::CreateVariation),
+            "DescribeImage" => Ok(Self::DescribeImage),
+            "Translate" => Ok(Self::Translate),
+            "Summarize" => Ok(Self::Summarize),
+            "Reminder" => Ok(Self::Reminder),
+            "Help" => Ok(Self::Help),
+            "Settings" => Ok(Self::Settings),
             _ => Err("Failed to convert to UserDemand"),
         }
     }
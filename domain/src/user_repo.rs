@@ -0,0 +1,25 @@
+use crate::user::UserPreferences;
+use mockall::automock;
+use std::future::Future;
+
+#[automock]
+pub trait UserRepo {
+    // returns the default preferences for users who never changed them
+    fn get_preferences(
+        &self,
+        user_id: String,
+    ) -> impl Future<Output = Result<UserPreferences, &'static str>>;
+    fn save_preferences(
+        &self,
+        user_id: String,
+        preferences: UserPreferences,
+    ) -> impl Future<Output = Result<(), &'static str>>;
+    fn delete_preferences(&self, user_id: String)
+        -> impl Future<Output = Result<(), &'static str>>;
+}
+
+pub trait ProvideUserRepo {
+    type Repo: UserRepo;
+
+    fn provide(&self) -> &Self::Repo;
+}
@@ -1,36 +1,118 @@
 use api_client::{
-    firestore::MessageRepoImpl,
-    gcs::Gcs,
     gpt::Gpt,
     line::{self, Line},
+    message_repo::MessageRepoImpl,
+    moderation::ModerationImpl,
+    object_storage::ObjectStorageImpl,
+    tools::ToolRegistry,
+    usage::UsageMeter,
+    usage_repo::UsageRepoImpl,
+    user_repo::UserRepoImpl,
 };
-use domain::{Actor, Context, Image, ImageMessage, Message, MessageRepo, UserDemand};
-use futures::stream::{self, StreamExt};
+use chrono::{DateTime, Duration, Utc};
+use domain::{
+    Actor, Context, Image, ImageMessage, ImageProvenance, Message, MessageQuery, MessageRepo,
+    Moderation, ObjectStorage, UsageQuery, UsageRecord, UsageRepo, UsageSummary, UserDemand,
+    UserRepo,
+};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::{
+    demand::{DemandHandlers, DemandRequest},
+    export::{self, ExportFormat},
+};
+
+// chat command that deletes everything stored about the user
+pub const DELETE_COMMAND: &str = "/delete";
+// chat command that sends the user a transcript, optionally followed by a format
+pub const EXPORT_COMMAND: &str = "/export";
+// chat command that shows the user what their messages have cost
+pub const USAGE_COMMAND: &str = "/usage";
+// days summed up by the usage command, the latest of them are listed one by one
+const USAGE_DAYS: i64 = 30;
+const USAGE_LISTED_DAYS: usize = 7;
+// messages before the latest one that the demand is classified with
+const DEMAND_HISTORY: usize = 4;
+// demands detected with less confidence are answered as chat
+const MIN_DEMAND_CONFIDENCE: f64 = 0.5;
+// postback of the collage quick replies, followed by the original and preview object names
+pub const FULL_SIZE_COMMAND: &str = "/full";
 
 #[derive(Clone)]
 pub struct App {
     pub llm_client: Gpt,
     pub message_client: Line,
-    pub storage_client: Gcs,
+    pub storage_client: ObjectStorageImpl,
     pub message_repo: MessageRepoImpl,
+    pub user_repo: UserRepoImpl,
+    pub usage_repo: UsageRepoImpl,
+    pub tools: ToolRegistry,
+    pub demands: DemandHandlers,
+    pub moderation: ModerationImpl,
+    // bot replies are checked too, not only the user's messages
+    pub moderate_output: bool,
+    pub retention_days: Option<i64>,
+    pub upload_concurrency: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub retention_days: i64,
+    pub cutoff: DateTime<Utc>,
+    pub messages: u64,
+    pub images: u64,
 }
 
 impl App {
     pub async fn new() -> Result<Self, &'static str> {
         let llm_client = Gpt::new().expect("Failed to initialize OpenAI API client");
         let message_client = Line::new().expect("Failed to initialize LINE client");
-        let storage_client = Gcs::new()
+        let storage_client = ObjectStorageImpl::new()
             .await
-            .expect("Failed to initialize Cloud Storage client");
+            .expect("Failed to initialize object storage client");
         let message_repo = MessageRepoImpl::new()
             .await
             .expect("Failed to initialize message repository");
+        let user_repo = UserRepoImpl::new()
+            .await
+            .expect("Failed to initialize user repository");
+        let usage_repo = UsageRepoImpl::new()
+            .await
+            .expect("Failed to initialize usage repository");
+        let tools = ToolRegistry::builtin(message_repo.clone());
+        let demands = DemandHandlers::new()?;
+        let moderation = ModerationImpl::new().expect("Failed to initialize moderation");
+        let moderate_output = std::env::var("MODERATE_OUTPUT")
+            .map(|moderate| moderate.parse().expect("Failed to parse MODERATE_OUTPUT"))
+            .unwrap_or(false);
+        let retention_days = std::env::var("RETENTION_DAYS")
+            .ok()
+            .map(|days| days.parse().expect("Failed to parse RETENTION_DAYS"));
+        let upload_concurrency = std::env::var("UPLOAD_CONCURRENCY")
+            .map(|concurrency| {
+                concurrency
+                    .parse()
+                    .expect("Failed to parse UPLOAD_CONCURRENCY")
+            })
+            .unwrap_or(4);
 
         Ok(Self {
             llm_client,
             message_client,
             storage_client,
             message_repo,
+            user_repo,
+            usage_repo,
+            tools,
+            demands,
+            moderation,
+            moderate_output,
+            retention_days,
+            upload_concurrency,
         })
     }
 
@@ -38,13 +120,85 @@ impl App {
         &self,
         payload: line::schema::WebhookEvent,
     ) -> Result<(), &'static str> {
-        let user_message = self.parse_user_message(payload)?;
+        // users who blocked the bot get their data deleted
+        for user_id in self.message_client.get_unfollowed_user_ids(&payload) {
+            self.purge_user_data(user_id).await?;
+        }
+
+        let user_image = self.message_client.get_user_image(&payload).await?;
+        let Some(user_message) = self.parse_user_message(payload)? else {
+            return Ok(());
+        };
         log::info!("User message: {:#?}", user_message);
 
+        if let Some(data) = user_image {
+            return self.receive_image(user_message, data).await;
+        }
+
+        if user_message.text.trim() == DELETE_COMMAND {
+            return self.delete_user_data(user_message).await;
+        }
+        if let Some(format) = command_argument(&user_message.text, EXPORT_COMMAND) {
+            let format = format.to_string();
+            return self.send_export(user_message, &format).await;
+        }
+        if user_message.text.trim() == USAGE_COMMAND {
+            return self.send_usage(user_message).await;
+        }
+        if let Some(names) = command_argument(&user_message.text, FULL_SIZE_COMMAND) {
+            let names = names.to_string();
+            return self.send_full_size(user_message, &names).await;
+        }
+
+        // flagged messages are neither routed nor saved
+        let moderation = self.moderation.moderate(user_message.text.clone()).await?;
+        if moderation.flagged {
+            log::warn!(
+                target: "moderation",
+                "Flagged user message from {}: {:?}, categories: {:?}",
+                user_message.user.id,
+                user_message.text,
+                moderation.categories
+            );
+            return self.refuse(user_message).await;
+        }
+
         self.show_loading_to_user().await?;
         log::trace!("Loading message sent to user");
 
-        let (context, user_demand) = self.detect_user_demand(&user_message).await?;
+        let preferences = self
+            .user_repo
+            .get_preferences(user_message.user.id.clone())
+            .await?;
+        log::trace!("Preferences: {:#?}", preferences);
+
+        // users who opted out of history are neither saved nor given their history
+        let history = if preferences.history_opt_out {
+            None
+        } else {
+            let history = self
+                .message_repo
+                // get the recent 10 messages(5 conversations)
+                .list_by_user_id(
+                    user_message.user.id.clone(),
+                    10,
+                    // get latest message at the bottom
+                    domain::OrderDirection::Ascending,
+                )
+                .await
+                .expect("Failed to get messages history");
+            log::trace!("History: {:#?}", history);
+
+            Some(history)
+        };
+
+        // every model call from here on is accounted to the user
+        let meter = UsageMeter::new(user_message.user.id.clone());
+        let llm_client = self.llm_client.metered(&meter);
+
+        let (context, user_demand) = self
+            .detect_user_demand(&llm_client, &user_message, history.as_deref())
+            .await?;
         log::info!("Context: {:#?}", context);
         log::info!("User demand: {:#?}", user_demand);
 
@@ -54,28 +208,28 @@ impl App {
             ..user_message.clone()
         };
 
-        // save user message to DB
-        self.save_messages(vec![user_message.clone()])
-            .await
-            .expect("Failed to save user message to DB");
-
-        let history = self
-            .message_repo
-            // get the recent 10 messages(5 conversations)
-            .list_by_user_id(
-                user_message.user.id.clone(),
-                10,
-                // get latest message at the bottom
-                domain::OrderDirection::Ascending,
-            )
-            .await
-            .expect("Failed to get messages history");
-        log::trace!("History: {:#?}", history);
+        if !preferences.history_opt_out {
+            // save user message to DB
+            self.save_messages(vec![user_message.clone()])
+                .await
+                .expect("Failed to save user message to DB");
+        }
 
-        let bot_response = match user_demand {
-            UserDemand::Chat => self.chat(&user_message, Some(history)).await?,
-            UserDemand::CreateImage => self.create_image(&user_message, Some(history)).await?,
-        };
+        let bot_response = self
+            .demands
+            .handle(DemandRequest {
+                app: self,
+                llm_client: &llm_client,
+                message: &user_message,
+                demand: &user_demand,
+                history,
+                preferences: &preferences,
+            })
+            .await;
+        // calls are paid for even when the reply fails
+        self.save_usage(&meter, &user_message).await;
+        let bot_response = bot_response?;
+        let bot_response = self.moderate_bot_response(bot_response).await?;
         log::info!("Bot message: {:#?}", bot_response);
 
         // reply chat to LINE
@@ -86,9 +240,11 @@ impl App {
         log::trace!("Message API response: {:#?}", message_api_response);
 
         // save bot response to DB
-        self.save_messages(bot_response)
-            .await
-            .expect("Failed to save bot response to DB");
+        if !preferences.history_opt_out {
+            self.save_messages(bot_response)
+                .await
+                .expect("Failed to save bot response to DB");
+        }
 
         Ok(())
     }
@@ -96,7 +252,7 @@ impl App {
     fn parse_user_message(
         &self,
         payload: line::schema::WebhookEvent,
-    ) -> Result<Message, &'static str> {
+    ) -> Result<Option<Message>, &'static str> {
         let user_message = self
             .message_client
             .get_user_message(payload)
@@ -116,132 +272,295 @@ impl App {
 
     async fn detect_user_demand(
         &self,
+        llm_client: &Gpt,
         message: &Message,
+        history: Option<&[Message]>,
     ) -> Result<(Context, UserDemand), &'static str> {
-        let user_demand = self
-            .llm_client
-            .detect_demand(message.text.clone())
+        // a few recent messages are enough to tell what "it" refers to
+        let history = history.unwrap_or_default();
+        let recent = &history[history.len().saturating_sub(DEMAND_HISTORY)..];
+        let detection = llm_client
+            .detect_demand([recent, std::slice::from_ref(message)].concat())
             .await
             .expect("Failed to detect user demand");
+        log::info!("Demand confidence: {}", detection.confidence);
 
-        Ok(user_demand)
+        // an unsure guess is safer answered as plain chat
+        if detection.confidence < MIN_DEMAND_CONFIDENCE {
+            return Ok((detection.context, UserDemand::Chat));
+        }
+
+        Ok((detection.context, detection.demand))
     }
 
-    async fn chat(
+    // images sent by the user become the latest image, so they can be edited next
+    async fn receive_image(
         &self,
-        message: &Message,
-        history: Option<Vec<Message>>,
-    ) -> Result<Vec<Message>, &'static str> {
-        let messages = vec![history.unwrap_or(vec![]), vec![message.clone()]].concat();
-        let bot_response = self
-            .llm_client
-            .chat(messages)
-            .await
-            .expect("Failed to get LLM response");
+        user_message: Message,
+        data: Vec<u8>,
+    ) -> Result<(), &'static str> {
+        let preferences = self
+            .user_repo
+            .get_preferences(user_message.user.id.clone())
+            .await?;
 
-        Ok(vec![Message {
-            from: Actor::Bot,
-            text: bot_response,
-            ..message.clone()
-        }])
-    }
+        let text = if preferences.history_opt_out {
+            "Your history is turned off, so I can't keep this image to edit it."
+        } else {
+            let image = self
+                .upload_single_image(&Image::from_bytes(data)?, None, &user_message.user.id)
+                .await?;
+            self.save_messages(vec![Message {
+                context: Some(Context::new("Image".to_string())),
+                image: Some(image),
+                ..user_message.clone()
+            }])
+            .await?;
+
+            "Got your image! Tell me how you'd like it changed."
+        };
 
-    async fn create_image(
-        &self,
-        message: &Message,
-        history: Option<Vec<Message>>,
-    ) -> Result<Vec<Message>, &'static str> {
-        let prompt = self.create_image_prompt(message, history).await?;
-        let base64_images = self
-            .llm_client
-            .generate_image(prompt)
+        let reply = Message {
+            from: Actor::Bot,
+            text: text.to_string(),
+            timestamp: None,
+            ..user_message.clone()
+        };
+        self.reply(&[reply], user_message.reply_token)
             .await
-            .expect("Failed to generate image");
+            .expect("Failed to send chat to LINE API");
 
-        let images: Vec<Image> = base64_images
-            .into_iter()
-            .map(|image| Image::from_base64(image))
-            .collect();
+        Ok(())
+    }
 
-        let previews: Vec<Image> = images
-            .clone()
-            .into_iter()
-            .map(|image| image.to_preview())
-            .collect();
+    pub async fn latest_image(&self, user_id: &str) -> Result<Option<Image>, &'static str> {
+        let Some(name) = self
+            .latest_image_message(user_id)
+            .await?
+            .and_then(|latest| latest.image)
+            .and_then(|image| image.name)
+        else {
+            return Ok(None);
+        };
 
-        let image_urls = stream::iter(images)
-            .then(|img| async {
-                self.upload_image(img)
-                    .await
-                    .expect("Failed to upload image")
-            })
-            .collect::<Vec<String>>()
-            .await;
+        Ok(Some(Image::from_bytes(
+            self.storage_client.get(name).await?,
+        )?))
+    }
 
-        let preview_urls = stream::iter(previews)
-            .then(|img| async {
-                self.upload_image(img)
-                    .await
-                    .expect("Failed to upload image")
+    // contexts change with every message, so this is the latest image of the user
+    async fn latest_image_message(&self, user_id: &str) -> Result<Option<Message>, &'static str> {
+        let page = self
+            .message_repo
+            .query(MessageQuery {
+                user_id: Some(user_id.to_string()),
+                has_image: Some(true),
+                limit: 1,
+                ..Default::default()
             })
-            .collect::<Vec<String>>()
-            .await;
+            .await?;
 
-        Ok(image_urls
-            .into_iter()
-            .zip(preview_urls.into_iter())
-            .map(|(original, preview)| ImageMessage {
-                url: original,
-                preview_url: preview,
-            })
-            .map(|img_msg| Message {
-                from: Actor::Bot,
-                text: "".to_string(),
-                image: Some(img_msg),
-                ..message.clone()
-            })
-            .collect())
+        Ok(page.messages.into_iter().next())
     }
 
-    async fn create_image_prompt(
-        &self,
-        message: &Message,
-        history: Option<Vec<Message>>,
-    ) -> Result<String, &'static str> {
-        let messages = if let Some(history) = history {
-            vec![history, vec![message.clone()]].concat()
-        } else {
-            vec![message.clone()]
+    async fn send_full_size(&self, user_message: Message, names: &str) -> Result<(), &'static str> {
+        let [original, preview] = names.split_whitespace().collect::<Vec<&str>>()[..] else {
+            return Err("Invalid full size request");
         };
-        let prompt = self.llm_client.create_image_prompt(messages).await?;
+        // the names come back from the client, so only the user's own images are served
+        let prefix = user_object_prefix(&user_message.user.id);
+        if !original.starts_with(&prefix) || !preview.starts_with(&prefix) {
+            return Err("Image belongs to another user");
+        }
+
+        let reply = self
+            .image_reply(&user_message, original.to_string(), preview.to_string())
+            .await?;
+        self.reply(
+            std::slice::from_ref(&reply),
+            user_message.reply_token.clone(),
+        )
+        .await
+        .expect("Failed to send chat to LINE API");
+
+        // saved so that follow-up edits apply to the picked image instead of the collage
+        let preferences = self
+            .user_repo
+            .get_preferences(user_message.user.id.clone())
+            .await?;
+        if !preferences.history_opt_out {
+            // postbacks carry no context, the picked image belongs to the collage's
+            let context = self
+                .latest_image_message(&user_message.user.id)
+                .await?
+                .and_then(|latest| latest.context);
+            if let Some(context) = context {
+                self.save_messages(vec![Message {
+                    context: Some(context),
+                    ..reply
+                }])
+                .await?;
+            }
+        }
 
-        Ok(prompt)
+        Ok(())
     }
 
-    async fn upload_image(&self, image: Image) -> Result<String, &'static str> {
-        let remote_file_object = self
-            .storage_client
-            .upload(
-                image.file_name(),
-                image.to_bytes().expect("Failed to get image bytes"),
-            )
+    async fn refuse(&self, user_message: Message) -> Result<(), &'static str> {
+        self.reply(
+            &[refusal_reply(&user_message)],
+            user_message.reply_token.clone(),
+        )
+        .await
+        .expect("Failed to send chat to LINE API");
+
+        Ok(())
+    }
+
+    // only text is checked, generated images were already screened by the images API
+    async fn moderate_bot_response(
+        &self,
+        messages: Vec<Message>,
+    ) -> Result<Vec<Message>, &'static str> {
+        if !self.moderate_output {
+            return Ok(messages);
+        }
+
+        let mut moderated = vec![];
+        for message in messages {
+            if message.text.is_empty() {
+                moderated.push(message);
+                continue;
+            }
+
+            let moderation = self.moderation.moderate(message.text.clone()).await?;
+            if moderation.flagged {
+                log::warn!(
+                    target: "moderation",
+                    "Flagged bot message to {}: {:?}, categories: {:?}",
+                    message.user.id,
+                    message.text,
+                    moderation.categories
+                );
+                moderated.push(refusal_reply(&message));
+            } else {
+                moderated.push(message);
+            }
+        }
+
+        Ok(moderated)
+    }
+
+    // uploaded concurrently, the names come back in the order of the images
+    pub async fn upload_images(
+        &self,
+        images: Vec<Image>,
+        user_id: &str,
+    ) -> Result<Vec<String>, &'static str> {
+        stream::iter(images)
+            .map(|img| self.upload_image(img, user_id))
+            .buffered(self.upload_concurrency.max(1))
+            .try_collect()
             .await
-            .expect("Failed to upload image");
+    }
+
+    // encodes and uploads one image along with its preview
+    pub async fn upload_single_image(
+        &self,
+        image: &Image,
+        provenance: Option<&ImageProvenance>,
+        user_id: &str,
+    ) -> Result<ImageMessage, &'static str> {
+        let image = match provenance {
+            Some(provenance) => image.with_provenance(provenance)?,
+            None => image.clone(),
+        };
+        let images = vec![image.to_original()?, image.to_preview()?];
+
+        let mut names = self.upload_images(images, user_id).await?;
+        let preview = names.pop().ok_or("Failed to upload image")?;
+        let original = names.pop().ok_or("Failed to upload image")?;
+
+        self.image_message(original, preview).await
+    }
+
+    // returns the object name, URLs are resolved when the image is sent
+    async fn upload_image(&self, image: Image, user_id: &str) -> Result<String, &'static str> {
+        log::trace!(
+            "Image: {}x{} {}, {} bytes",
+            image.width,
+            image.height,
+            image.format.mime_type(),
+            image.size()
+        );
+
+        // images are grouped by user so they can be purged together, and named by their
+        // pixels rather than their bytes: every file embeds its own provenance, so no two
+        // would share a byte hash. identical pixels keep the provenance of the first upload
+        let name = format!(
+            "{}{}.{}",
+            user_object_prefix(user_id),
+            image.content_hash()?,
+            image.format.extension()
+        );
+
+        let remote_file_object = match self.storage_client.stat(name.clone()).await? {
+            Some(existing) => existing,
+            None => {
+                self.storage_client
+                    .put(name, image.format.mime_type().to_string(), image.data)
+                    .await?
+            }
+        };
         log::trace!("Remote file object: {:#?}", remote_file_object);
 
-        let download_url = self
+        Ok(remote_file_object.name)
+    }
+
+    async fn image_message(
+        &self,
+        original_name: String,
+        preview_name: String,
+    ) -> Result<ImageMessage, &'static str> {
+        let url = self
             .storage_client
-            .get_url(remote_file_object)
+            .url(original_name.clone())
             .await
             .expect("Falied to get image URL");
-        log::trace!("Download URL: {:#?}", download_url);
+        let preview_url = self
+            .storage_client
+            .url(preview_name.clone())
+            .await
+            .expect("Falied to get image URL");
+        log::trace!("Download URLs: {:#?}, {:#?}", url, preview_url);
+
+        Ok(ImageMessage {
+            url,
+            preview_url,
+            name: Some(original_name),
+            preview_name: Some(preview_name),
+            revised_prompt: None,
+        })
+    }
 
-        Ok(download_url)
+    pub async fn image_reply(
+        &self,
+        message: &Message,
+        original_name: String,
+        preview_name: String,
+    ) -> Result<Message, &'static str> {
+        Ok(Message {
+            from: Actor::Bot,
+            text: "".to_string(),
+            image: Some(self.image_message(original_name, preview_name).await?),
+            timestamp: None,
+            ..message.clone()
+        })
     }
 
     async fn reply(
         &self,
-        messages: &Vec<Message>,
+        messages: &[Message],
         reply_token: Option<String>,
     ) -> Result<reqwest::Response, &'static str> {
         let line_messages = messages
@@ -265,4 +584,337 @@ impl App {
     async fn save_messages(&self, messages: Vec<Message>) -> Result<(), &'static str> {
         self.message_repo.save(messages).await
     }
+
+    async fn delete_user_data(&self, user_message: Message) -> Result<(), &'static str> {
+        self.purge_user_data(user_message.user.id.clone()).await?;
+
+        // the confirmation is not saved, otherwise the user would have history again
+        let confirmation = Message {
+            from: Actor::Bot,
+            text: "All of your messages and images have been deleted.".to_string(),
+            timestamp: None,
+            ..user_message.clone()
+        };
+        self.reply(&[confirmation], user_message.reply_token)
+            .await
+            .expect("Failed to send chat to LINE API");
+
+        Ok(())
+    }
+
+    pub async fn purge_user_data(&self, user_id: String) -> Result<(), &'static str> {
+        let images = self
+            .storage_client
+            .list(Some(user_object_prefix(&user_id)))
+            .await?;
+        for image in images.iter() {
+            self.storage_client.delete(image.name.clone()).await?;
+        }
+
+        let messages = self.message_repo.delete_by_user_id(user_id.clone()).await?;
+        self.usage_repo.delete_by_user_id(user_id.clone()).await?;
+        self.user_repo.delete_preferences(user_id.clone()).await?;
+        log::info!(
+            "Purged data of user {}: {} messages, {} images",
+            user_id,
+            messages,
+            images.len()
+        );
+
+        Ok(())
+    }
+
+    pub async fn apply_retention(&self, dry_run: bool) -> Result<RetentionReport, &'static str> {
+        let retention_days = self.retention_days.ok_or("RETENTION_DAYS is not set")?;
+        let cutoff = Utc::now() - Duration::days(retention_days);
+
+        // an image is stored once however many messages show it, so an old object
+        // is kept as long as a message that stays still refers to it
+        let referenced = self
+            .list_all_messages(MessageQuery {
+                since: Some(cutoff),
+                has_image: Some(true),
+                ..Default::default()
+            })
+            .await?
+            .into_iter()
+            .filter_map(|message| message.image)
+            .flat_map(|image| [image.name, image.preview_name])
+            .flatten()
+            .collect::<HashSet<String>>();
+        let images = self
+            .storage_client
+            .list(None)
+            .await?
+            .into_iter()
+            .filter(|image| image.created_time.is_some_and(|created| created < cutoff))
+            .filter(|image| !referenced.contains(&image.name))
+            .collect::<Vec<_>>();
+
+        let messages = if dry_run {
+            self.message_repo
+                .count(MessageQuery {
+                    until: Some(cutoff),
+                    ..Default::default()
+                })
+                .await?
+        } else {
+            for image in images.iter() {
+                self.storage_client.delete(image.name.clone()).await?;
+            }
+            self.message_repo.delete_created_before(cutoff).await?
+        };
+
+        let report = RetentionReport {
+            dry_run,
+            retention_days,
+            cutoff,
+            messages,
+            images: images.len() as u64,
+        };
+        log::info!("Retention report: {:#?}", report);
+
+        Ok(report)
+    }
+
+    // accounting never fails the conversation, lost records are only logged
+    async fn save_usage(&self, meter: &UsageMeter, message: &Message) {
+        let context_id = message.context.as_ref().map(|context| context.id.clone());
+        let records = meter
+            .take()
+            .into_iter()
+            .map(|record| UsageRecord {
+                context_id: context_id.clone(),
+                ..record
+            })
+            .collect::<Vec<UsageRecord>>();
+        log::trace!("Usage: {:#?}", records);
+
+        if let Err(error) = self.usage_repo.save(records).await {
+            log::error!("Failed to save usage: {}", error);
+        }
+    }
+
+    pub async fn usage(&self, query: UsageQuery) -> Result<Vec<UsageSummary>, &'static str> {
+        let records = self.usage_repo.query(query).await?;
+
+        Ok(UsageSummary::by_day(&records))
+    }
+
+    async fn send_usage(&self, user_message: Message) -> Result<(), &'static str> {
+        let summaries = self
+            .usage(UsageQuery {
+                user_id: Some(user_message.user.id.clone()),
+                since: Some(Utc::now() - Duration::days(USAGE_DAYS)),
+                ..Default::default()
+            })
+            .await?;
+
+        // the usage summary is not saved, it is outdated by the next message
+        let reply = Message {
+            from: Actor::Bot,
+            text: usage_text(&summaries),
+            timestamp: None,
+            ..user_message.clone()
+        };
+        self.reply(&[reply], user_message.reply_token)
+            .await
+            .expect("Failed to send chat to LINE API");
+
+        Ok(())
+    }
+
+    async fn send_export(&self, user_message: Message, format: &str) -> Result<(), &'static str> {
+        let format = match format {
+            "" => ExportFormat::Markdown,
+            format => match ExportFormat::try_from(format) {
+                Ok(format) => format,
+                Err(_) => {
+                    let reply = Message {
+                        from: Actor::Bot,
+                        text: format!(
+                            "Unknown export format \"{}\". Send {} for Markdown or {} json for JSON.",
+                            format, EXPORT_COMMAND, EXPORT_COMMAND
+                        ),
+                        timestamp: None,
+                        ..user_message.clone()
+                    };
+                    self.reply(&[reply], user_message.reply_token)
+                        .await
+                        .expect("Failed to send chat to LINE API");
+
+                    return Ok(());
+                }
+            },
+        };
+
+        let user_id = user_message.user.id.clone();
+        let content = self
+            .export(
+                MessageQuery {
+                    user_id: Some(user_id.clone()),
+                    ..Default::default()
+                },
+                format,
+            )
+            .await?;
+
+        let remote_file_object = self
+            .storage_client
+            .put(
+                // the random suffix keeps the export URL unguessable on public storage
+                format!(
+                    "{}exports/{}-{}.{}",
+                    user_object_prefix(&user_id),
+                    Utc::now().format("%Y%m%d%H%M%S"),
+                    Uuid::new_v4(),
+                    format.extension()
+                ),
+                format.content_type().to_string(),
+                content.into_bytes(),
+            )
+            .await?;
+        let download_url = self.storage_client.url(remote_file_object.name).await?;
+
+        // the export link is not saved, signed links expire anyway
+        let validity = self
+            .storage_client
+            .url_expiration()
+            .map(|expiration| format!(" (valid for {} minutes)", expiration.as_secs() / 60))
+            .unwrap_or_default();
+        let reply = Message {
+            from: Actor::Bot,
+            text: format!(
+                "Here is your conversation history{}:\n{}",
+                validity, download_url
+            ),
+            timestamp: None,
+            ..user_message.clone()
+        };
+        self.reply(&[reply], user_message.reply_token)
+            .await
+            .expect("Failed to send chat to LINE API");
+
+        Ok(())
+    }
+
+    pub async fn export(
+        &self,
+        query: MessageQuery,
+        format: ExportFormat,
+    ) -> Result<String, &'static str> {
+        let mut messages = self
+            .list_all_messages(MessageQuery {
+                order_direction: domain::OrderDirection::Ascending,
+                ..query
+            })
+            .await?;
+
+        // the stored URLs expired long ago, so the images are signed again from their names
+        for image in messages
+            .iter_mut()
+            .filter_map(|message| message.image.as_mut())
+        {
+            if let Some(name) = image.name.clone() {
+                image.url = self.storage_client.url(name).await?;
+                // images saved before previews were named link the original in their place
+                image.preview_url = match image.preview_name.clone() {
+                    Some(preview_name) => self.storage_client.url(preview_name).await?,
+                    None => image.url.clone(),
+                };
+            }
+        }
+
+        export::render(&messages, format)
+    }
+
+    async fn list_all_messages(&self, query: MessageQuery) -> Result<Vec<Message>, &'static str> {
+        let mut messages = vec![];
+        let mut page_token = None;
+        loop {
+            let page = self
+                .message_repo
+                .query(MessageQuery {
+                    limit: 500,
+                    page_token,
+                    ..query.clone()
+                })
+                .await?;
+
+            messages.extend(page.messages);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(messages)
+    }
+}
+
+pub fn refusal_reply(message: &Message) -> Message {
+    Message {
+        from: Actor::Bot,
+        text: "Sorry, I can't help with that request.".to_string(),
+        image: None,
+        quick_replies: vec![],
+        timestamp: None,
+        ..message.clone()
+    }
+}
+
+fn usage_text(summaries: &[UsageSummary]) -> String {
+    if summaries.is_empty() {
+        return format!("You have no usage in the last {} days.", USAGE_DAYS);
+    }
+
+    let line = |label: String, summary: &UsageSummary| {
+        format!(
+            "{}: {} requests, {} tokens, {} images, about ${:.4}",
+            label,
+            summary.requests,
+            summary.total_tokens(),
+            summary.images,
+            summary.cost
+        )
+    };
+    let total = summaries
+        .iter()
+        .cloned()
+        .reduce(|total, summary| UsageSummary {
+            requests: total.requests + summary.requests,
+            prompt_tokens: total.prompt_tokens + summary.prompt_tokens,
+            completion_tokens: total.completion_tokens + summary.completion_tokens,
+            images: total.images + summary.images,
+            cost: total.cost + summary.cost,
+            ..total
+        })
+        .expect("Summaries are not empty");
+    let days = summaries
+        .iter()
+        .rev()
+        .take(USAGE_LISTED_DAYS)
+        .map(|summary| line(summary.day.to_string(), summary))
+        .collect::<Vec<String>>();
+
+    format!(
+        "Your usage (UTC days):\n{}\n\n{}",
+        days.join("\n"),
+        line(format!("Last {} days", USAGE_DAYS), &total)
+    )
+}
+
+// the text after the command, None when the message is not the command
+fn command_argument<'a>(text: &'a str, command: &str) -> Option<&'a str> {
+    let argument = text.trim().strip_prefix(command)?;
+    // "/exporter" is not "/export"
+    if !argument.is_empty() && !argument.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some(argument.trim())
+}
+
+fn user_object_prefix(user_id: &str) -> String {
+    format!("users/{}/", user_id)
 }
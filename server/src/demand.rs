@@ -0,0 +1,152 @@
+mod chat;
+mod describe;
+mod edit;
+mod help;
+mod image;
+mod reminder;
+mod settings;
+mod summarize;
+mod translate;
+
+use api_client::gpt::Gpt;
+use domain::{Actor, Message, UserDemand, UserPreferences};
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use crate::app::App;
+
+pub type HandlerFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<Message>, &'static str>> + Send + 'a>>;
+
+// everything a handler gets to answer the user's message with
+pub struct DemandRequest<'a> {
+    pub app: &'a App,
+    // accounts its calls to the user, preferred over the app's client
+    pub llm_client: &'a Gpt,
+    pub message: &'a Message,
+    pub demand: &'a UserDemand,
+    // None when the user opted out of history
+    pub history: Option<Vec<Message>>,
+    pub preferences: &'a UserPreferences,
+}
+
+// answers one kind of demand, the replies are moderated, sent and saved by the app
+pub trait DemandHandler: Send + Sync {
+    // as in UserDemand::name
+    fn demand(&self) -> &'static str;
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a>;
+
+    // listed in the help when given
+    fn help(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct DemandHandlers {
+    handlers: Vec<Arc<dyn DemandHandler>>,
+}
+
+impl DemandHandlers {
+    // all built-in handlers except the ones listed in DISABLED_DEMANDS
+    pub fn new() -> Result<Self, &'static str> {
+        let disabled = std::env::var("DISABLED_DEMANDS").unwrap_or_default();
+
+        Self::builtin().disable(
+            disabled
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty()),
+        )
+    }
+
+    pub fn builtin() -> Self {
+        Self::default()
+            .register(chat::Chat)
+            .register(image::CreateImage)
+            .register(edit::EditImage)
+            .register(image::RefineImage)
+            .register(image::CreateVariation)
+            .register(describe::DescribeImage)
+            .register(translate::Translate)
+            .register(summarize::Summarize)
+            .register(reminder::Reminder)
+            .register(settings::Settings)
+            .register(help::Help)
+    }
+
+    // a handler for a demand already registered replaces it
+    pub fn register(mut self, handler: impl DemandHandler + 'static) -> Self {
+        self.handlers
+            .retain(|registered| registered.demand() != handler.demand());
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    pub fn disable<'a>(
+        mut self,
+        demands: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, &'static str> {
+        for demand in demands {
+            // every other demand falls back to chat, so it can't go
+            if demand == UserDemand::Chat.name() {
+                return Err("Chat can't be disabled");
+            }
+            if self.get(demand).is_none() {
+                return Err("Unknown demand to disable");
+            }
+            self.handlers
+                .retain(|registered| registered.demand() != demand);
+        }
+
+        Ok(self)
+    }
+
+    pub fn get(&self, demand: &str) -> Option<&dyn DemandHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.demand() == demand)
+            .map(|handler| handler.as_ref())
+    }
+
+    // help lines of the enabled handlers, in the order they were registered
+    pub fn help(&self) -> Vec<&'static str> {
+        self.handlers
+            .iter()
+            .filter_map(|handler| handler.help())
+            .collect()
+    }
+
+    // disabled demands are answered as chat
+    pub async fn handle(&self, request: DemandRequest<'_>) -> Result<Vec<Message>, &'static str> {
+        let handler = match self.get(request.demand.name()) {
+            Some(handler) => handler,
+            None => {
+                log::info!("{} is disabled, answering as chat", request.demand.name());
+                self.get(UserDemand::Chat.name())
+                    .ok_or("No chat handler registered")?
+            }
+        };
+
+        handler.handle(request).await
+    }
+}
+
+fn text_reply(message: &Message, text: String) -> Message {
+    Message {
+        from: Actor::Bot,
+        text,
+        image: None,
+        quick_replies: vec![],
+        timestamp: None,
+        ..message.clone()
+    }
+}
+
+fn no_image_reply(message: &Message) -> Message {
+    Message {
+        from: Actor::Bot,
+        text: "There is no image to edit yet.".to_string(),
+        timestamp: None,
+        ..message.clone()
+    }
+}
@@ -0,0 +1,51 @@
+use api_client::{gpt::ChatOptions, tools::ToolContext};
+use domain::{Message, UserDemand};
+
+use super::{text_reply, DemandHandler, DemandRequest, HandlerFuture};
+
+pub struct Chat;
+
+impl DemandHandler for Chat {
+    fn demand(&self) -> &'static str {
+        UserDemand::Chat.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let options = ChatOptions::from(request.preferences);
+            chat(request, &options).await
+        })
+    }
+}
+
+// shared with the handlers that are chat with an extra instruction
+pub async fn chat(
+    request: DemandRequest<'_>,
+    options: &ChatOptions,
+) -> Result<Vec<Message>, &'static str> {
+    let message = request.message;
+    // the name only personalizes the reply, so the chat goes on without it
+    let user_name = request
+        .app
+        .message_client
+        .get_display_name(&message.user.id)
+        .await
+        .unwrap_or_else(|error| {
+            log::warn!("Failed to get display name: {}", error);
+            None
+        });
+    let options = options.clone().with_user_name(user_name);
+    // no history is handed over when the user opted out of it
+    let context = ToolContext {
+        user_id: message.user.id.clone(),
+        history_opt_out: request.history.is_none(),
+    };
+    let messages = [request.history.unwrap_or_default(), vec![message.clone()]].concat();
+    let bot_response = request
+        .llm_client
+        .chat_with_tools(messages, &options, &request.app.tools, &context)
+        .await
+        .expect("Failed to get LLM response");
+
+    Ok(vec![text_reply(message, bot_response)])
+}
@@ -0,0 +1,36 @@
+use api_client::gpt::ChatOptions;
+use domain::UserDemand;
+
+use super::{no_image_reply, text_reply, DemandHandler, DemandRequest, HandlerFuture};
+
+pub struct DescribeImage;
+
+impl DemandHandler for DescribeImage {
+    fn demand(&self) -> &'static str {
+        UserDemand::DescribeImage.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let message = request.message;
+            let Some(image) = request.app.latest_image(&message.user.id).await? else {
+                return Ok(vec![no_image_reply(message)]);
+            };
+
+            let description = request
+                .llm_client
+                .describe_image(
+                    &image,
+                    message.text.clone(),
+                    &ChatOptions::from(request.preferences),
+                )
+                .await?;
+
+            Ok(vec![text_reply(message, description)])
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("describe the latest image or one you send me")
+    }
+}
@@ -0,0 +1,56 @@
+use domain::{Actor, Message, UserDemand};
+
+use super::{no_image_reply, DemandHandler, DemandRequest, HandlerFuture};
+
+pub struct EditImage;
+
+impl DemandHandler for EditImage {
+    fn demand(&self) -> &'static str {
+        UserDemand::EditImage(vec![]).name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let edits = match request.demand {
+                UserDemand::EditImage(edits) if !edits.is_empty() => edits,
+                // nothing to apply, so it is answered like any other message
+                _ => {
+                    let chat = UserDemand::Chat;
+                    return request
+                        .app
+                        .demands
+                        .handle(DemandRequest {
+                            demand: &chat,
+                            ..request
+                        })
+                        .await;
+                }
+            };
+
+            let message = request.message;
+            let Some(source) = request.app.latest_image(&message.user.id).await? else {
+                return Ok(vec![no_image_reply(message)]);
+            };
+
+            // edited locally, no image generation API is involved
+            let edited = source.edit(edits)?;
+            // the edit keeps the record of how its source was generated
+            let image = request
+                .app
+                .upload_single_image(&edited, source.provenance().as_ref(), &message.user.id)
+                .await?;
+
+            Ok(vec![Message {
+                from: Actor::Bot,
+                text: "".to_string(),
+                image: Some(image),
+                timestamp: None,
+                ..message.clone()
+            }])
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("edit the latest image, e.g. crop, rotate or turn it black and white")
+    }
+}
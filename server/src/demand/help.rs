@@ -0,0 +1,34 @@
+use domain::UserDemand;
+
+use super::{text_reply, DemandHandler, DemandRequest, HandlerFuture};
+use crate::app::{DELETE_COMMAND, EXPORT_COMMAND, USAGE_COMMAND};
+
+pub struct Help;
+
+impl DemandHandler for Help {
+    fn demand(&self) -> &'static str {
+        UserDemand::Help.name()
+    }
+
+    // only what is enabled is listed
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let abilities = request
+                .app
+                .demands
+                .help()
+                .into_iter()
+                .map(|help| format!("- {}", help))
+                .collect::<Vec<String>>();
+            let text = format!(
+                "I can chat, answer questions and:\n{}\n\nCommands:\n{} [markdown|json] sends you your history\n{} shows what your messages have cost\n{} deletes everything stored about you",
+                abilities.join("\n"),
+                EXPORT_COMMAND,
+                USAGE_COMMAND,
+                DELETE_COMMAND
+            );
+
+            Ok(vec![text_reply(request.message, text)])
+        })
+    }
+}
@@ -0,0 +1,301 @@
+use api_client::{
+    gpt::{GeneratedImages, ImageOptions, ImagePrompt, CONTENT_POLICY_VIOLATION},
+    line::MAX_TEXT_LENGTH,
+};
+use chrono::Utc;
+use domain::{Image, ImageError, ImageProvenance, Message, QuickReply, UserDemand};
+
+use super::{no_image_reply, text_reply, DemandHandler, DemandRequest, HandlerFuture};
+use crate::app::{refusal_reply, FULL_SIZE_COMMAND};
+
+pub struct CreateImage;
+
+impl DemandHandler for CreateImage {
+    fn demand(&self) -> &'static str {
+        UserDemand::CreateImage.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let options = ImageOptions::from(request.preferences);
+            let message = request.message;
+            let image_prompt = create_image_prompt(&request).await?;
+            // what the message asks for wins over the user's preferences
+            let options = image_prompt.options.or(&options);
+            let generated = match request
+                .llm_client
+                .generate_image(image_prompt.prompt.clone(), &options)
+                .await
+            {
+                Err(error) if error == CONTENT_POLICY_VIOLATION => {
+                    return Ok(vec![rejected_image_reply(message, &image_prompt.prompt)]);
+                }
+                Err(error) => {
+                    return Ok(vec![failed_image_reply(
+                        message,
+                        error,
+                        "Sorry, I couldn't draw the image right now. Please try again later.",
+                    )]);
+                }
+                Ok(generated) => generated,
+            };
+
+            generated_image_replies(&request, image_prompt.prompt, generated).await
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("create images")
+    }
+}
+
+pub struct RefineImage;
+
+impl DemandHandler for RefineImage {
+    fn demand(&self) -> &'static str {
+        UserDemand::RefineImage.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let options = ImageOptions::from(request.preferences);
+            let (app, message) = (request.app, request.message);
+            let Some(source) = app.latest_image(&message.user.id).await? else {
+                return Ok(vec![no_image_reply(message)]);
+            };
+
+            let image_prompt = create_image_prompt(&request).await?;
+            let options = image_prompt.options.or(&options);
+            let generated = match request
+                .llm_client
+                .edit_image(&source, None, image_prompt.prompt.clone(), &options)
+                .await
+            {
+                Err(error) if error == CONTENT_POLICY_VIOLATION => {
+                    return Ok(vec![rejected_image_reply(message, &image_prompt.prompt)]);
+                }
+                // e.g. the account has no access to the edit model
+                Err(error) => {
+                    return Ok(vec![failed_image_reply(
+                        message,
+                        error,
+                        "Sorry, I couldn't redraw the latest image. Please describe the whole \
+                        image you want instead.",
+                    )]);
+                }
+                Ok(generated) => generated,
+            };
+
+            generated_image_replies(&request, image_prompt.prompt, generated).await
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("redraw the latest image the way you describe")
+    }
+}
+
+pub struct CreateVariation;
+
+impl DemandHandler for CreateVariation {
+    fn demand(&self) -> &'static str {
+        UserDemand::CreateVariation.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let options = ImageOptions::from(request.preferences);
+            let (app, message) = (request.app, request.message);
+            let Some(source) = app.latest_image(&message.user.id).await? else {
+                return Ok(vec![no_image_reply(message)]);
+            };
+
+            // variations have no prompt of their own, so the source's is kept
+            let prompt = source
+                .provenance()
+                .map(|provenance| provenance.prompt)
+                .unwrap_or_default();
+            let generated = match request.llm_client.create_variation(&source, &options).await {
+                Err(error) if error == CONTENT_POLICY_VIOLATION => {
+                    return Ok(vec![rejected_image_reply(message, &prompt)]);
+                }
+                Err(error) => {
+                    return Ok(vec![failed_image_reply(
+                        message,
+                        error,
+                        "Sorry, I couldn't draw variations of the latest image right now. \
+                        Please try again later.",
+                    )]);
+                }
+                Ok(generated) => generated,
+            };
+
+            generated_image_replies(&request, prompt, generated).await
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("draw variations of the latest image")
+    }
+}
+
+async fn create_image_prompt(request: &DemandRequest<'_>) -> Result<ImagePrompt, &'static str> {
+    let messages = [
+        request.history.clone().unwrap_or_default(),
+        vec![request.message.clone()],
+    ]
+    .concat();
+
+    request.llm_client.create_image_prompt(messages).await
+}
+
+async fn generated_image_replies(
+    request: &DemandRequest<'_>,
+    prompt: String,
+    generated: GeneratedImages,
+) -> Result<Vec<Message>, &'static str> {
+    let (app, message) = (request.app, request.message);
+    // recorded in every uploaded file so outputs can be audited and reproduced
+    let provenance = ImageProvenance {
+        prompt,
+        model: generated.model,
+        size: generated.size,
+        created_time: Utc::now(),
+        context_id: message.context.as_ref().map(|context| context.id.clone()),
+    };
+
+    let revised_prompts = generated
+        .images
+        .iter()
+        .map(|image| image.revised_prompt.clone())
+        .collect::<Vec<Option<String>>>();
+    let images = generated
+        .images
+        .into_iter()
+        .map(|image| Image::from_base64(image.b64_json)?.with_provenance(&provenance))
+        .collect::<Result<Vec<Image>, ImageError>>()?;
+
+    // LINE limits the byte size of both originals and previews
+    let originals = images
+        .iter()
+        .map(Image::to_original)
+        .collect::<Result<Vec<Image>, ImageError>>()?;
+    let previews = images
+        .iter()
+        .map(Image::to_preview)
+        .collect::<Result<Vec<Image>, ImageError>>()?;
+
+    // several images are replied as one numbered grid instead of a wall of bubbles
+    let collage = if images.len() > 1 {
+        let collage = Image::collage(&images)?.with_provenance(&provenance)?;
+        vec![collage.to_original()?, collage.to_preview()?]
+    } else {
+        vec![]
+    };
+
+    let image_count = originals.len();
+    let mut image_names = app
+        .upload_images([originals, previews, collage].concat(), &message.user.id)
+        .await?;
+    let collage_names = image_names.split_off(image_count * 2);
+    let preview_names = image_names.split_off(image_count);
+
+    if let [collage_name, collage_preview_name] = collage_names.as_slice() {
+        // the object names travel in the postback, so no state is kept for the pick
+        let quick_replies = image_names
+            .iter()
+            .zip(preview_names.iter())
+            .enumerate()
+            .map(|(index, (original, preview))| QuickReply {
+                // LINE caps quick reply labels at 20 characters
+                label: format!("Full size #{}", index + 1),
+                data: format!("{} {} {}", FULL_SIZE_COMMAND, original, preview),
+            })
+            .collect();
+
+        let revised_prompts = revised_prompts
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, prompt)| Some(format!("#{} {}", index + 1, prompt?)))
+            .collect::<Vec<String>>();
+        let mut collage = Message {
+            quick_replies,
+            ..app
+                .image_reply(message, collage_name.clone(), collage_preview_name.clone())
+                .await?
+        };
+        // numbered like the grid, so the history keeps what each image was drawn from
+        if let Some(image) = collage.image.as_mut() {
+            image.revised_prompt =
+                (!revised_prompts.is_empty()).then(|| revised_prompts.join("\n\n"));
+        }
+
+        return Ok([
+            vec![collage],
+            revised_prompt_reply(message, revised_prompts),
+        ]
+        .concat());
+    }
+
+    let mut messages = vec![];
+    for ((original, preview), revised_prompt) in image_names
+        .into_iter()
+        .zip(preview_names)
+        .zip(revised_prompts)
+    {
+        let mut reply = app.image_reply(message, original, preview).await?;
+        if let Some(image) = reply.image.as_mut() {
+            image.revised_prompt = revised_prompt.clone();
+        }
+        messages.push(reply);
+        messages.extend(revised_prompt_reply(
+            message,
+            revised_prompt.into_iter().collect(),
+        ));
+    }
+
+    Ok(messages)
+}
+
+// the images API refusing a prompt is answered like a flagged message instead of failing
+fn rejected_image_reply(message: &Message, prompt: &str) -> Message {
+    log::warn!(
+        target: "moderation",
+        "Image prompt rejected for {}: {:?}",
+        message.user.id,
+        prompt
+    );
+
+    refusal_reply(message)
+}
+
+// other failures of the images API, such as rate limits, are answered instead of failing the turn
+fn failed_image_reply(message: &Message, error: &str, text: &str) -> Message {
+    log::error!("Image request failed for {}: {}", message.user.id, error);
+
+    text_reply(message, text.to_string())
+}
+
+// shows what the model actually drew so the prompt can be copied and reused
+fn revised_prompt_reply(message: &Message, revised_prompts: Vec<String>) -> Vec<Message> {
+    if revised_prompts.is_empty() {
+        return vec![];
+    }
+
+    // up to 10 prompts of several hundred characters each can exceed a text message
+    let mut text = revised_prompts.join("\n\n");
+    if text.encode_utf16().count() > MAX_TEXT_LENGTH {
+        let mut length = 0;
+        let end = text
+            .char_indices()
+            .find(|(_, character)| {
+                length += character.len_utf16();
+                length > MAX_TEXT_LENGTH - 1
+            })
+            .map_or(text.len(), |(index, _)| index);
+        text.truncate(end);
+        text.push('…');
+    }
+
+    vec![text_reply(message, text)]
+}
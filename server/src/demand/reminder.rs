@@ -0,0 +1,61 @@
+use api_client::line;
+use chrono::{Duration, Utc};
+use domain::UserDemand;
+
+use super::{text_reply, DemandHandler, DemandRequest, HandlerFuture};
+
+// reminders further out than a week are brought forward to it
+const MAX_REMINDER_MINUTES: u32 = 7 * 24 * 60;
+
+pub struct Reminder;
+
+impl DemandHandler for Reminder {
+    fn demand(&self) -> &'static str {
+        UserDemand::Reminder.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (app, message) = (request.app, request.message);
+            let reminder = request
+                .llm_client
+                .extract_reminder(message.text.clone())
+                .await?;
+            let Some(minutes) = reminder.minutes_from_now else {
+                return Ok(vec![text_reply(
+                    message,
+                    "When should I remind you?".to_string(),
+                )]);
+            };
+            let minutes = minutes.clamp(1, MAX_REMINDER_MINUTES);
+            let remind_time = Utc::now() + Duration::minutes(minutes.into());
+
+            // kept in memory only, so pending reminders are lost when the server restarts
+            let message_client = app.message_client.clone();
+            let user_id = message.user.id.clone();
+            let text = reminder.text.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_secs(u64::from(minutes) * 60)).await;
+                if let Err(error) = message_client
+                    .send_messages(user_id, vec![line::schema::Message::text(text, None)])
+                    .await
+                {
+                    log::error!("Failed to send reminder: {}", error);
+                }
+            });
+
+            Ok(vec![text_reply(
+                message,
+                format!(
+                    "I'll remind you of \"{}\" at {} UTC.",
+                    reminder.text,
+                    remind_time.format("%Y-%m-%d %H:%M")
+                ),
+            )])
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("remind you of something later")
+    }
+}
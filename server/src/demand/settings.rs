@@ -0,0 +1,79 @@
+use domain::{UserDemand, UserPreferences, UserRepo};
+
+use super::{text_reply, DemandHandler, DemandRequest, HandlerFuture};
+
+pub struct Settings;
+
+impl DemandHandler for Settings {
+    fn demand(&self) -> &'static str {
+        UserDemand::Settings.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let (app, message) = (request.app, request.message);
+            let mut preferences = request.preferences.clone();
+            let update = request
+                .llm_client
+                .extract_settings(message.text.clone())
+                .await?;
+            // asking to see the settings changes nothing
+            if !update.is_empty() {
+                update.apply(&mut preferences);
+                app.user_repo
+                    .save_preferences(message.user.id.clone(), preferences.clone())
+                    .await?;
+            }
+
+            Ok(vec![text_reply(message, settings_text(&preferences))])
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("change settings such as my reply language, persona and image size")
+    }
+}
+
+fn settings_text(preferences: &UserPreferences) -> String {
+    let or_default = |value: Option<String>| value.unwrap_or("default".to_string());
+    [
+        format!(
+            "Reply language: {}",
+            or_default(preferences.reply_language.clone())
+        ),
+        format!("Persona: {}", or_default(preferences.persona.clone())),
+        format!(
+            "Verbosity: {}",
+            or_default(
+                preferences
+                    .verbosity
+                    .map(|verbosity| format!("{:?}", verbosity))
+            )
+        ),
+        format!(
+            "Image size: {}",
+            or_default(preferences.image_size.map(|size| format!("{:?}", size)))
+        ),
+        format!(
+            "Image orientation: {}",
+            or_default(
+                preferences
+                    .image_orientation
+                    .map(|orientation| format!("{:?}", orientation))
+            )
+        ),
+        format!(
+            "Image count: {}",
+            or_default(preferences.image_count.map(|count| count.to_string()))
+        ),
+        format!(
+            "History: {}",
+            if preferences.history_opt_out {
+                "off"
+            } else {
+                "on"
+            }
+        ),
+    ]
+    .join("\n")
+}
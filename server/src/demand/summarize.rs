@@ -0,0 +1,23 @@
+use api_client::gpt::{ChatOptions, ChatTask};
+use domain::UserDemand;
+
+use super::{chat::chat, DemandHandler, DemandRequest, HandlerFuture};
+
+pub struct Summarize;
+
+impl DemandHandler for Summarize {
+    fn demand(&self) -> &'static str {
+        UserDemand::Summarize.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let options = ChatOptions::from(request.preferences).with_task(ChatTask::Summarize);
+            chat(request, &options).await
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("summarize texts")
+    }
+}
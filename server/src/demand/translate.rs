@@ -0,0 +1,23 @@
+use api_client::gpt::{ChatOptions, ChatTask};
+use domain::UserDemand;
+
+use super::{chat::chat, DemandHandler, DemandRequest, HandlerFuture};
+
+pub struct Translate;
+
+impl DemandHandler for Translate {
+    fn demand(&self) -> &'static str {
+        UserDemand::Translate.name()
+    }
+
+    fn handle<'a>(&'a self, request: DemandRequest<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let options = ChatOptions::from(request.preferences).with_task(ChatTask::Translate);
+            chat(request, &options).await
+        })
+    }
+
+    fn help(&self) -> Option<&'static str> {
+        Some("translate texts")
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use domain::{Actor, Message};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+impl TryFrom<&str> for ExportFormat {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            _ => Err("Invalid export format, expected markdown or json"),
+        }
+    }
+}
+
+// messages are expected in chronological order
+pub fn render(messages: &[Message], format: ExportFormat) -> Result<String, &'static str> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(messages)),
+        ExportFormat::Json => {
+            let messages = messages
+                .iter()
+                .map(ExportedMessage::from)
+                .collect::<Vec<ExportedMessage>>();
+
+            serde_json::to_string_pretty(&messages).map_err(|_| "Failed to render JSON export")
+        }
+    }
+}
+
+fn render_markdown(messages: &[Message]) -> String {
+    let mut markdown = "# Conversation\n".to_string();
+    let mut context_id = None;
+
+    for message in messages {
+        // start a new section whenever the topic changes
+        if let Some(context) = &message.context {
+            if context_id.as_ref() != Some(&context.id) {
+                markdown.push_str(&format!("\n## {}\n", context.name));
+                context_id = Some(context.id.clone());
+            }
+        }
+
+        let sender = match message.from {
+            Actor::User => "User",
+            Actor::Bot => "UNAI",
+        };
+        let timestamp = message
+            .timestamp
+            .map(|timestamp| format!(" ({})", timestamp.format("%Y-%m-%d %H:%M:%S UTC")))
+            .unwrap_or_default();
+        markdown.push_str(&format!("\n**{}**{}\n\n", sender, timestamp));
+
+        if !message.text.is_empty() {
+            markdown.push_str(&format!("{}\n", message.text));
+        }
+        if let Some(image) = &message.image {
+            markdown.push_str(&format!(
+                "[![image]({})]({})\n",
+                image.preview_url, image.url
+            ));
+        }
+    }
+
+    markdown
+}
+
+#[derive(Serialize)]
+struct ExportedMessage {
+    from: &'static str,
+    text: String,
+    timestamp: Option<DateTime<Utc>>,
+    context_id: Option<String>,
+    context_name: Option<String>,
+    image_url: Option<String>,
+    preview_image_url: Option<String>,
+}
+
+impl From<&Message> for ExportedMessage {
+    fn from(message: &Message) -> Self {
+        Self {
+            from: match message.from {
+                Actor::User => "user",
+                Actor::Bot => "bot",
+            },
+            text: message.text.clone(),
+            timestamp: message.timestamp,
+            context_id: message
+                .context
+                .as_ref()
+                .map(|context| context.id.to_string()),
+            context_name: message.context.as_ref().map(|context| context.name.clone()),
+            image_url: message.image.as_ref().map(|image| image.url.clone()),
+            preview_image_url: message
+                .image
+                .as_ref()
+                .map(|image| image.preview_url.clone()),
+        }
+    }
+}
@@ -1,13 +1,20 @@
 mod app;
+mod demand;
+mod export;
 
-use app::App;
+use api_client::{filesystem, line, object_storage::ObjectStorageImpl};
+use app::{App, RetentionReport};
 use axum::{
-    extract::Extension,
-    http::StatusCode,
+    extract::{Extension, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
-use api_client::line;
+use chrono::{NaiveDate, NaiveTime};
+use domain::{ContextId, MessageQuery, ObjectStorage, UsageQuery, UsageSummary};
+use export::ExportFormat;
+use serde::{Deserialize, Serialize};
 
 #[tokio::main]
 async fn main() -> Result<(), &'static str> {
@@ -21,6 +28,11 @@ async fn main() -> Result<(), &'static str> {
     let router = Router::new()
         .route("/", get(|| async { "Welcome to UNAI API!" }))
         .route("/conversation", post(conversation))
+        .route("/admin/retention", post(retention))
+        .route("/admin/export", get(export))
+        .route("/admin/prompts/reload", post(reload_prompts))
+        .route("/admin/usage", get(usage))
+        .route("/storage/*name", get(storage))
         .layer(Extension(app));
 
     // run our app with hyper, listening globally on port 8080
@@ -49,3 +61,184 @@ async fn conversation(
 
     Ok(StatusCode::OK)
 }
+
+// serves objects of the local filesystem storage, other backends hand out their own URLs
+async fn storage(
+    Extension(app): Extension<App>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, &'static str)> {
+    let ObjectStorageImpl::FileSystem(storage) = &app.storage_client else {
+        return Err((StatusCode::NOT_FOUND, "Local storage is disabled"));
+    };
+
+    let content_type = filesystem::content_type(&name);
+    let data = storage
+        .get(name)
+        .await
+        .map_err(|err| (StatusCode::NOT_FOUND, err))?;
+
+    Ok(([(header::CONTENT_TYPE, content_type)], data))
+}
+
+#[derive(Deserialize)]
+struct RetentionParams {
+    dry_run: Option<bool>,
+}
+
+async fn retention(
+    Extension(app): Extension<App>,
+    headers: HeaderMap,
+    Query(params): Query<RetentionParams>,
+) -> Result<Json<RetentionReport>, (StatusCode, &'static str)> {
+    authorize_admin(&headers)?;
+
+    let report = app
+        .apply_retention(params.dry_run.unwrap_or(true))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+struct ExportParams {
+    user_id: Option<String>,
+    context_id: Option<String>,
+    format: Option<String>,
+}
+
+async fn export(
+    Extension(app): Extension<App>,
+    headers: HeaderMap,
+    Query(params): Query<ExportParams>,
+) -> Result<impl IntoResponse, (StatusCode, &'static str)> {
+    authorize_admin(&headers)?;
+
+    if params.user_id.is_none() && params.context_id.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "user_id or context_id is required"));
+    }
+    let context_id = params
+        .context_id
+        .map(ContextId::try_from)
+        .transpose()
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let format = params
+        .format
+        .as_deref()
+        .map(ExportFormat::try_from)
+        .transpose()
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?
+        .unwrap_or(ExportFormat::Json);
+
+    let content = app
+        .export(
+            MessageQuery {
+                user_id: params.user_id,
+                context_id,
+                ..Default::default()
+            },
+            format,
+        )
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(([(header::CONTENT_TYPE, format.content_type())], content))
+}
+
+#[derive(Deserialize)]
+struct UsageParams {
+    user_id: Option<String>,
+    // first and last UTC day to sum up, both included
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+}
+
+#[derive(Serialize)]
+struct UsageDay {
+    user_id: String,
+    day: NaiveDate,
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    images: u64,
+    cost: f64,
+}
+
+impl From<UsageSummary> for UsageDay {
+    fn from(summary: UsageSummary) -> Self {
+        Self {
+            user_id: summary.user_id,
+            day: summary.day,
+            requests: summary.requests,
+            prompt_tokens: summary.prompt_tokens,
+            completion_tokens: summary.completion_tokens,
+            images: summary.images,
+            cost: summary.cost,
+        }
+    }
+}
+
+async fn usage(
+    Extension(app): Extension<App>,
+    headers: HeaderMap,
+    Query(params): Query<UsageParams>,
+) -> Result<Json<Vec<UsageDay>>, (StatusCode, &'static str)> {
+    authorize_admin(&headers)?;
+
+    let start_of_day = |day: NaiveDate| day.and_time(NaiveTime::MIN).and_utc();
+    let summaries = app
+        .usage(UsageQuery {
+            user_id: params.user_id,
+            since: params.since.map(start_of_day),
+            until: params
+                .until
+                .and_then(|until| until.succ_opt())
+                .map(start_of_day),
+        })
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(Json(summaries.into_iter().map(UsageDay::from).collect()))
+}
+
+// re-reads PROMPTS_DIR so edited templates and personas apply without a restart
+async fn reload_prompts(
+    Extension(app): Extension<App>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, &'static str)> {
+    authorize_admin(&headers)?;
+
+    app.llm_client
+        .prompts()
+        .reload()
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// admin endpoints require the ADMIN_TOKEN as a bearer token, an empty one disables them
+fn authorize_admin(headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    let admin_token = std::env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|admin_token| !admin_token.is_empty())
+        .ok_or((StatusCode::FORBIDDEN, "Admin endpoints are disabled"))?;
+
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    if !constant_time_eq(authorization, format!("Bearer {}", admin_token).as_bytes()) {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid admin token"));
+    }
+
+    Ok(())
+}
+
+// compares every byte so the time taken doesn't reveal how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}